@@ -0,0 +1,125 @@
+//! Role-based access control for contract entry points.
+//!
+//! Contracts include [`init_all`] in their named keys on install, forward the [`grant_role`],
+//! [`revoke_role`] and [`has_role`] entry points, and call [`ensure_role`] at the start of every
+//! privileged entry point. Only admins can grant and revoke roles.
+
+use alloc::format;
+use casper_types::{CLType, CLTyped};
+
+use crate::{
+    b64_cl,
+    call_stack::{self, CallStackElementEx},
+    prelude::*,
+    FromNamedArg,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Role {
+    /// Grants and revokes roles.
+    Admin = 0,
+    /// Manages the configuration of the contract.
+    Manager = 1,
+    /// Moves funds held by the contract.
+    Treasurer = 2,
+    /// Pauses and unpauses the contract.
+    Pauser = 3,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Admin, Role::Manager, Role::Treasurer, Role::Pauser];
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|role| *role as u8 == value)
+    }
+}
+
+impl ToBytes for Role {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        Ok(vec![*self as u8])
+    }
+
+    fn serialized_length(&self) -> usize {
+        1
+    }
+}
+
+impl FromBytes for Role {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (tag, rem) = u8::from_bytes(bytes)?;
+        let role = Role::from_u8(tag).ok_or(bytesrepr::Error::Formatting)?;
+        Ok((role, rem))
+    }
+}
+
+impl CLTyped for Role {
+    fn cl_type() -> CLType {
+        CLType::U8
+    }
+}
+
+impl FromNamedArg for Role {}
+
+named_keys! {
+    init_keys():
+    dict roles: bool;
+}
+
+fn role_key(role: Role, account: Key) -> String {
+    format!("{}{}", role as u8, b64_cl(&account))
+}
+
+fn set_role(role: Role, account: Key, value: bool) {
+    if value {
+        roles::write(&role_key(role, account), true);
+    } else {
+        roles::remove(&role_key(role, account));
+    }
+}
+
+/// Create the named keys of the module, with every role granted to `admin`.
+pub fn init_all(admin: Key) -> Vec<(String, Key)> {
+    let keys = init_keys();
+    for role in Role::ALL {
+        set_role(role, admin, true);
+    }
+
+    keys
+}
+
+/// Like [`init_all`], but only if the roles dictionary doesn't exist yet. For use in migrations.
+pub fn init_all_missing(admin: Key) {
+    if runtime::get_key(roles::NAME).is_none() {
+        for (name, key) in init_all(admin) {
+            runtime::put_key(&name, key);
+        }
+    }
+}
+
+/// Return whether `account` holds `role`.
+pub fn has_role(role: Role, account: Key) -> bool {
+    roles::try_read(&role_key(role, account)).unwrap_or(false)
+}
+
+/// Revert with [`CommonError::MissingRole`] unless the caller holds `role`.
+pub fn ensure_role(role: Role) {
+    ensure!(
+        has_role(role, call_stack::caller().key()),
+        CommonError::MissingRole
+    );
+}
+
+/// Grant `role` to `account`. Only callable by admins.
+pub fn grant_role(role: Role, account: Key) {
+    ensure_role(Role::Admin);
+    set_role(role, account, true);
+}
+
+/// Revoke `role` from `account`. Only callable by admins.
+///
+/// Admins can revoke their own admin role, so make sure another admin exists first.
+pub fn revoke_role(role: Role, account: Key) {
+    ensure_role(Role::Admin);
+    set_role(role, account, false);
+}
@@ -2,7 +2,7 @@
 use casper_types::system::CallStackElement;
 use once_cell::unsync::Lazy;
 
-use crate::prelude::*;
+use crate::{error::CallStackError, prelude::*};
 
 st_non_sync_static! {
     Lazy<alloc::vec::Vec<CallStackElement>> = Lazy::new(|| {
@@ -27,9 +27,9 @@ pub fn at_depth(depth: usize) -> Option<&'static CallStackElement> {
 
 /// Return the context of the immediate caller of the current context.
 ///
-/// Reverts with [`ApiError::User(u16::MAX)`] if there is no immediate caller.
+/// Reverts with [`CallStackError::NoCaller`] if there is no immediate caller.
 pub fn caller() -> &'static CallStackElement {
-    at_depth(1).unwrap_or_revert_with(ApiError::User(u16::MAX))
+    at_depth(1).unwrap_or_revert_with(CallStackError::NoCaller)
 }
 
 /// Return the current context.
@@ -40,7 +40,7 @@ pub fn current() -> &'static CallStackElement {
 
 fn current_contract_full() -> (&'static ContractPackageHash, &'static ContractHash) {
     match current() {
-        CallStackElement::Session { .. } => revert(ApiError::User(u16::MAX)),
+        CallStackElement::Session { .. } => revert(CallStackError::NotAContract),
         CallStackElement::StoredSession {
             contract_package_hash,
             contract_hash,
@@ -55,14 +55,16 @@ fn current_contract_full() -> (&'static ContractPackageHash, &'static ContractHa
 
 /// Return the current contract's package hash.
 ///
-/// Reverts with [`ApiError::User(u16::MAX)`] if the current context doesn't reference a contract.
+/// Reverts with [`CallStackError::NotAContract`] if the current context doesn't reference a
+/// contract.
 pub fn current_package() -> ContractPackageHash {
     *current_contract_full().0
 }
 
 /// Return the current contract's package hash.
 ///
-/// Reverts with [`ApiError::User(u16::MAX)`] if the current context doesn't reference a contract.
+/// Reverts with [`CallStackError::NotAContract`] if the current context doesn't reference a
+/// contract.
 pub fn current_contract() -> ContractHash {
     *current_contract_full().1
 }
@@ -81,3 +81,36 @@ pub fn new_dictionary_anon() -> URef {
     let value_bytes = read_host_buffer(value_size).unwrap_or_revert();
     bytesrepr::deserialize(value_bytes).unwrap_or_revert()
 }
+
+/// Like `storage::dictionary_get`, but returns the serialized value. Entries that were removed by
+/// overwriting them with `()` read as `None`.
+pub fn dictionary_get_bytes(
+    dictionary_seed_uref: URef,
+    dictionary_item_key: &str,
+) -> Option<Vec<u8>> {
+    let uref_bytes = dictionary_seed_uref.to_bytes().unwrap_or_revert();
+    let key_bytes = dictionary_item_key.as_bytes();
+    let value_size = {
+        let mut value_size = MaybeUninit::uninit();
+        let ret = unsafe {
+            ext_ffi::casper_dictionary_get(
+                uref_bytes.as_ptr(),
+                uref_bytes.len(),
+                key_bytes.as_ptr(),
+                key_bytes.len(),
+                value_size.as_mut_ptr(),
+            )
+        };
+        match api_error::result_from(ret) {
+            Ok(_) => unsafe { value_size.assume_init() },
+            Err(ApiError::ValueNotFound) => return None,
+            Err(e) => revert(e),
+        }
+    };
+
+    if value_size == 0 {
+        return None;
+    }
+
+    Some(read_host_buffer(value_size).unwrap_or_revert())
+}
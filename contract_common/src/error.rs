@@ -1,22 +1,139 @@
-use crate::prelude::*;
+use crate::{ext::cep78::Cep78Error, prelude::*};
 
+/// Number of `ApiError::User` codes in each [`ErrorRange`].
+pub const ERROR_RANGE_SIZE: u16 = 256;
+
+/// The ranges of `ApiError::User` codes, one per error enum.
+///
+/// Range `n` covers the codes `n * ERROR_RANGE_SIZE..(n + 1) * ERROR_RANGE_SIZE`. The first range
+/// holds the codes of CEP-78, which counts up from 1, and the last one those of ERC-20, which
+/// counts down from `u16::MAX`, so neither is used and every code decodes to one error, whichever
+/// contract it came from.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u16)]
-pub enum CommonError {
-    InvalidTokenIdentifier,
-    InvalidMethodAccess,
+pub enum ErrorRange {
+    Common = 1,
+    Custodial = 2,
+    Market = 3,
+    CallStack = 4,
+}
+
+impl ErrorRange {
+    pub const ALL: [ErrorRange; 4] = [
+        ErrorRange::Common,
+        ErrorRange::Custodial,
+        ErrorRange::Market,
+        ErrorRange::CallStack,
+    ];
+
+    /// First code of the range.
+    pub const fn base(self) -> u16 {
+        self as u16 * ERROR_RANGE_SIZE
+    }
+
+    /// Name of the error enum the range belongs to. `user_errors!` refuses to compile any other
+    /// enum in it.
+    pub const fn owner(self) -> &'static str {
+        match self {
+            ErrorRange::Common => "CommonError",
+            ErrorRange::Custodial => "CustodialError",
+            ErrorRange::Market => "MarketError",
+            ErrorRange::CallStack => "CallStackError",
+        }
+    }
+
+    /// Return the range `code` belongs to, if any.
+    pub fn of(code: u16) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|range| range.base() == code - code % ERROR_RANGE_SIZE)
+    }
+}
+
+#[doc(hidden)]
+pub const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+/// An error enum declared with `user_errors!`.
+pub trait UserError: Copy + Sized + 'static {
+    const RANGE: ErrorRange;
+    const VARIANTS: &'static [Self];
+
+    /// Name of the enum variant.
+    fn name(self) -> &'static str;
+
+    /// Code of the error in `ApiError::User`.
+    fn code(self) -> u16;
 
-    InvalidCacheSet,
+    /// Decode an `ApiError::User` code, if it belongs to this enum.
+    fn from_code(code: u16) -> Option<Self> {
+        Self::VARIANTS
+            .iter()
+            .copied()
+            .find(|error| error.code() == code)
+    }
+}
+
+/// Return the name of the variant of `E` an `ApiError::User` code stands for.
+pub fn variant_name<E: UserError>(code: u16) -> Option<&'static str> {
+    E::from_code(code).map(E::name)
 }
 
-impl From<CommonError> for ApiError {
-    fn from(error: CommonError) -> Self {
-        ApiError::User(error as u16)
+/// Return the name of the error enum and variant an `ApiError::User` code stands for.
+///
+/// The errors of CEP-78 and of this crate are always known. The error enums of the contracts are
+/// passed in as `variant_name::<E>`, since this crate can't see them.
+pub fn describe(
+    code: u16,
+    contract_errors: &[fn(u16) -> Option<&'static str>],
+) -> Option<(&'static str, &'static str)> {
+    if code < ERROR_RANGE_SIZE {
+        return Cep78Error::from_code(code).map(|error| ("Cep78Error", error.name()));
+    }
+
+    let range = ErrorRange::of(code)?;
+    let variant = [variant_name::<CommonError>, variant_name::<CallStackError>]
+        .iter()
+        .chain(contract_errors)
+        .find_map(|variant_name| variant_name(code))?;
+
+    Some((range.owner(), variant))
+}
+
+user_errors! {
+    pub enum CommonError in Common {
+        InvalidTokenIdentifier,
+        InvalidMethodAccess,
+
+        InvalidCacheSet,
+
+        ContractPaused,
+        MissingRole,
+        ReentrantCall,
+
+        InvalidAccessUref,
     }
 }
 
-impl From<CommonError> for u16 {
-    fn from(error: CommonError) -> Self {
-        error as u16
+user_errors! {
+    pub enum CallStackError in CallStack {
+        /// The current context has no caller.
+        NoCaller,
+        /// The current context is not a contract.
+        NotAContract,
     }
 }
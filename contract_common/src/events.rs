@@ -0,0 +1,46 @@
+//! Event log kept in the contract's own state.
+//!
+//! Contracts include [`init_all`] in their named keys on install and call [`emit`]. Every event is
+//! written to the `events` dictionary under its index, and `event_count` holds the number of
+//! events so far, so off-chain consumers can page through the log.
+
+use alloc::string::ToString;
+use casper_types::bytesrepr::Bytes;
+
+use crate::{prelude::*, r_unwrap};
+
+named_keys! {
+    init_all():
+    dict events: EventRecord;
+    val event_count: u64 = 0;
+}
+
+serializable_structs! {
+    /// An event as stored in the log: its name, and its fields serialized with `ToBytes`.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct EventRecord {
+        pub name: String,
+        pub data: Bytes,
+    }
+}
+
+pub trait Event: ToBytes {
+    const NAME: &'static str;
+}
+
+/// Append `event` to the log and return its index.
+pub fn emit<E: Event>(event: &E) -> u64 {
+    let index = event_count::read();
+    let data = r_unwrap!(event.to_bytes(), ApiError::Serialize);
+
+    events::write(
+        &index.to_string(),
+        EventRecord {
+            name: E::NAME.to_string(),
+            data: data.into(),
+        },
+    );
+    event_count::write(index + 1);
+
+    index
+}
@@ -1,9 +1,99 @@
+/// Client for CEP-78 collections.
+///
+/// A call that fails inside the collection reverts the whole deploy with the collection's own
+/// `ApiError::User` code, which [`cep78::Cep78Error`] decodes.
+///
+/// CEP-78 has no entry points for the token issuers or the transfer filter contract. The issuers
+/// are kept in a dictionary, which [`cep78::token_issuer`] reads directly once it's given the
+/// dictionary's address. The transfer filter contract is kept in a URef that only the collection
+/// itself can read, so contracts have to be told it.
 pub mod cep78 {
     use crate::token::TokenIdentifier;
-    use crate::{named_arg, trace_block};
-    use alloc::{string::String, vec};
-    use casper_contract::contract_api::runtime;
-    use casper_types::{ContractPackageHash, Key};
+    use crate::{named_arg, r_unwrap, trace_block};
+    use alloc::{
+        string::{String, ToString},
+        vec,
+    };
+    use casper_contract::contract_api::{runtime, storage};
+    use casper_types::{AccessRights, ApiError, ContractPackageHash, Key, URef, URefAddr};
+
+    macro_rules! cep78_errors {
+        ($( $variant:ident = $code:literal ),* $(,)?) => {
+            /// The `NFTCoreError` codes of CEP-78 v1.5 that the calls in this module can revert
+            /// with.
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+            #[repr(u16)]
+            pub enum Cep78Error {
+                $( $variant = $code ),*
+            }
+
+            impl Cep78Error {
+                pub const VARIANTS: &'static [Self] = &[$( Self::$variant ),*];
+
+                pub fn name(self) -> &'static str {
+                    match self {
+                        $( Self::$variant => stringify!($variant) ),*
+                    }
+                }
+            }
+        };
+    }
+
+    cep78_errors! {
+        InvalidAccount = 1,
+        MissingInstaller = 2,
+        InvalidInstaller = 3,
+        UnexpectedKeyVariant = 4,
+        MissingTokenOwner = 5,
+        InvalidTokenOwner = 6,
+        MissingTokenID = 27,
+        InvalidTokenIdentifier = 28,
+        TokenSupplyDepleted = 32,
+        InvalidMinter = 36,
+        InvalidBurner = 41,
+        PreviouslyBurntToken = 42,
+        MissingTokenMetaData = 47,
+        InvalidTokenMetaData = 48,
+        MissingApproveAll = 53,
+        InvalidApproveAll = 54,
+        MissingOperator = 55,
+        InvalidOperator = 56,
+        InvalidOwnershipMode = 57,
+        InvalidContract = 69,
+        InvalidJsonMetadata = 73,
+        UnregisteredOwnerInMint = 114,
+        UnregisteredOwnerInTransfer = 115,
+        MissingSpenderAccountHash = 140,
+        InvalidSpenderAccountHash = 141,
+        TransferFilterContractDenied = 146,
+    }
+
+    impl Cep78Error {
+        /// Decode an `ApiError::User` code a CEP-78 collection reverted with.
+        pub fn from_code(code: u16) -> Option<Self> {
+            Self::VARIANTS
+                .iter()
+                .copied()
+                .find(|error| *error as u16 == code)
+        }
+    }
+
+    impl From<Cep78Error> for u16 {
+        fn from(error: Cep78Error) -> Self {
+            error as u16
+        }
+    }
+
+    /// What `mint` returns.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct MintReceipt {
+        /// Name of the receipt the minter gets under its named keys.
+        pub receipt_name: String,
+        /// Key of the owner's entry in the owned tokens dictionary.
+        pub owned_tokens: Key,
+        /// Identifier of the new token, formatted according to the collection's identifier mode.
+        pub token_id: String,
+    }
 
     pub fn transfer(
         package: ContractPackageHash,
@@ -26,7 +116,7 @@ pub mod cep78 {
         }}
     }
 
-    pub fn metadata(package: ContractPackageHash, token_id: TokenIdentifier) -> String {
+    pub fn metadata(package: ContractPackageHash, token_id: &TokenIdentifier) -> String {
         trace_block! {{
             runtime::call_versioned_contract::<String>(
                 package,
@@ -58,6 +148,126 @@ pub mod cep78 {
             )
         }}
     }
+
+    pub fn balance_of(package: ContractPackageHash, token_owner: Key) -> u64 {
+        trace_block! {{
+            runtime::call_versioned_contract::<u64>(
+                package,
+                None,
+                "balance_of",
+                vec![named_arg!(token_owner)].into(),
+            )
+        }}
+    }
+
+    pub fn approve(package: ContractPackageHash, token_id: &TokenIdentifier, spender: Key) {
+        trace_block! {{
+            runtime::call_versioned_contract::<()>(
+                package,
+                None,
+                "approve",
+                vec![token_id.to_named_arg(), named_arg!(spender)].into(),
+            );
+        }}
+    }
+
+    pub fn revoke(package: ContractPackageHash, token_id: &TokenIdentifier) {
+        trace_block! {{
+            runtime::call_versioned_contract::<()>(
+                package,
+                None,
+                "revoke",
+                vec![token_id.to_named_arg()].into(),
+            );
+        }}
+    }
+
+    pub fn set_approval_for_all(package: ContractPackageHash, approve_all: bool, operator: Key) {
+        trace_block! {{
+            runtime::call_versioned_contract::<()>(
+                package,
+                None,
+                "set_approval_for_all",
+                vec![named_arg!(approve_all), named_arg!(operator)].into(),
+            );
+        }}
+    }
+
+    pub fn is_approved_for_all(
+        package: ContractPackageHash,
+        token_owner: Key,
+        operator: Key,
+    ) -> bool {
+        trace_block! {{
+            runtime::call_versioned_contract::<bool>(
+                package,
+                None,
+                "is_approved_for_all",
+                vec![named_arg!(token_owner), named_arg!(operator)].into(),
+            )
+        }}
+    }
+
+    /// Check whether `spender` may transfer `token_id` on behalf of `owner`, either through an
+    /// approval for that token or as an operator for all of `owner`'s tokens.
+    pub fn is_approved(
+        package: ContractPackageHash,
+        token_id: &TokenIdentifier,
+        owner: Key,
+        spender: Key,
+    ) -> bool {
+        get_approved(package, token_id) == Some(spender)
+            || is_approved_for_all(package, owner, spender)
+    }
+
+    pub fn burn(package: ContractPackageHash, token_id: &TokenIdentifier) {
+        trace_block! {{
+            runtime::call_versioned_contract::<()>(
+                package,
+                None,
+                "burn",
+                vec![token_id.to_named_arg()].into(),
+            );
+        }}
+    }
+
+    /// Read the account or contract that minted `token_id`, or `None` if it wasn't minted.
+    ///
+    /// `token_issuers` is the address of the collection's `token_issuers` dictionary. Contracts
+    /// can't look up another contract's named keys, so it has to be read off-chain once.
+    pub fn token_issuer(token_issuers: URefAddr, token_id: &TokenIdentifier) -> Option<Key> {
+        let item_key = match token_id {
+            TokenIdentifier::Index(index) => index.to_string(),
+            TokenIdentifier::Hash(hash) => hash.clone(),
+        };
+        let seed = URef::new(token_issuers, AccessRights::NONE);
+
+        r_unwrap!(
+            storage::dictionary_read::<Key>(Key::dictionary(seed, item_key.as_bytes())),
+            ApiError::Read
+        )
+    }
+
+    pub fn mint(
+        package: ContractPackageHash,
+        token_owner: Key,
+        token_meta_data: String,
+    ) -> MintReceipt {
+        let (receipt_name, owned_tokens, token_id) = trace_block! {{
+            runtime::call_versioned_contract::<(String, Key, String)>(
+                package,
+                None,
+                "mint",
+                vec![named_arg!(token_owner), named_arg!(token_meta_data)].into(),
+            )
+        }};
+
+        MintReceipt {
+            receipt_name,
+            owned_tokens,
+            token_id,
+        }
+    }
 }
 
 pub mod erc20 {
@@ -116,7 +326,7 @@ pub mod cep82 {
     pub mod custodial {
         use alloc::vec;
         use casper_contract::contract_api::runtime;
-        use casper_types::{ContractPackageHash, Key, URef, U512};
+        use casper_types::{ContractPackageHash, Key, RuntimeArgs, URef, U512};
 
         use crate::{named_arg, token::TokenIdentifier, trace_block};
 
@@ -168,5 +378,100 @@ pub mod cep82 {
                 )
             }}
         }
+
+        #[allow(clippy::too_many_arguments)]
+        pub fn settle_sale(
+            package: ContractPackageHash,
+            token_contract: ContractPackageHash,
+            token_id: &TokenIdentifier,
+            source_purse: URef,
+            payer: Key,
+            source_key: Key,
+            target_key: Key,
+            seller_purse: URef,
+        ) -> U512 {
+            trace_block! {{
+                runtime::call_versioned_contract::<U512>(
+                    package,
+                    None,
+                    "settle_sale",
+                    vec![
+                        named_arg!(token_contract),
+                        token_id.to_named_arg(),
+                        named_arg!(source_purse),
+                        named_arg!(payer),
+                        named_arg!(source_key),
+                        named_arg!(target_key),
+                        named_arg!(seller_purse),
+                    ].into(),
+                )
+            }}
+        }
+
+        pub fn settlement_required(package: ContractPackageHash) -> bool {
+            trace_block! {{
+                runtime::call_versioned_contract::<bool>(
+                    package,
+                    None,
+                    "settlement_required",
+                    RuntimeArgs::new(),
+                )
+            }}
+        }
+
+        pub fn pay_transfer_fee(
+            package: ContractPackageHash,
+            token_contract: ContractPackageHash,
+            token_id: &TokenIdentifier,
+            source_purse: URef,
+            target_key: Key,
+        ) {
+            trace_block! {{
+                runtime::call_versioned_contract::<()>(
+                    package,
+                    None,
+                    "pay_transfer_fee",
+                    vec![
+                        named_arg!(token_contract),
+                        token_id.to_named_arg(),
+                        named_arg!(source_purse),
+                        named_arg!(target_key),
+                    ].into(),
+                )
+            }}
+        }
+
+        pub fn transferable_at(package: ContractPackageHash, token_id: &TokenIdentifier) -> u64 {
+            trace_block! {{
+                runtime::call_versioned_contract::<u64>(
+                    package,
+                    None,
+                    "transferable_at",
+                    vec![token_id.to_named_arg()].into(),
+                )
+            }}
+        }
+
+        pub fn is_paused(package: ContractPackageHash) -> bool {
+            trace_block! {{
+                runtime::call_versioned_contract::<bool>(
+                    package,
+                    None,
+                    "is_paused",
+                    RuntimeArgs::new(),
+                )
+            }}
+        }
+
+        pub fn transfer_fee(package: ContractPackageHash) -> Option<U512> {
+            trace_block! {{
+                runtime::call_versioned_contract::<Option<U512>>(
+                    package,
+                    None,
+                    "transfer_fee",
+                    RuntimeArgs::new(),
+                )
+            }}
+        }
     }
 }
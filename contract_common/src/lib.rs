@@ -1,12 +1,15 @@
 #![no_std]
 
 use alloc::{format, string::ToString, vec::Vec};
-use casper_contract::contract_api::runtime;
+use casper_contract::contract_api::{runtime, storage};
 use casper_types::{
     bytesrepr::{FromBytes, ToBytes},
-    Key, URef,
+    contracts::NamedKeys,
+    AccessRights, ApiError, ContractHash, ContractPackageHash, EntryPoints, Key, RuntimeArgs, URef,
 };
 
+use crate::error::CommonError;
+
 extern crate alloc;
 
 pub mod prelude {
@@ -31,16 +34,21 @@ pub mod prelude {
     pub use crate::{
         b64, b64_cl, contract_api::try_get_named_arg, ensure, ensure_eq, ensure_neq, entrypoint,
         entrypoints, error::CommonError, forward_entrypoints, named_arg, named_key, named_keys,
-        serializable_structs, st_non_sync_static,
+        serializable_structs, st_non_sync_static, user_errors,
     };
 }
 
+pub mod access;
 pub mod call_stack;
 pub mod contract_api;
 pub mod error;
+pub mod events;
 pub mod ext;
 pub mod macros;
+pub mod pausable;
+pub mod reentrancy;
 pub mod token;
+pub mod versioned;
 
 pub fn b64<T: AsRef<[u8]>>(input: T) -> alloc::string::String {
     use base64::Engine;
@@ -67,6 +75,57 @@ pub fn store_named_key_incremented(key: Key, name: &str) {
     }
 }
 
+/// Name of the entry point that brings a contract's state up to date after an upgrade.
+pub const MIGRATE_ENTRY_POINT: &str = "migrate";
+
+/// Add a new version to an installed contract package, and run its `migrate` entry point with
+/// `migrate_args`.
+///
+/// The package is `package_hash` if given, or else the one the installer stored under
+/// `package_key`. Installers didn't always store the package hash, so older packages have to be
+/// passed explicitly.
+///
+/// Must be called from the session code of the account that installed the package, with the
+/// package access URef stored under `access_key`. The host only adds the version if that URef is
+/// the package's access URef, so this just checks that the key holds a URef with full access.
+///
+/// The new version inherits the named keys of the previous one, which is where all the contract
+/// state lives. Its contract hash is stored under `{contract_key}_v{version}`, next to the hashes
+/// of earlier versions.
+pub fn upgrade_contract_package(
+    package_hash: Option<ContractPackageHash>,
+    package_key: &str,
+    access_key: &str,
+    contract_key: &str,
+    entry_points: EntryPoints,
+    migrate_args: RuntimeArgs,
+) -> ContractHash {
+    let package_hash = o_unwrap!(
+        package_hash.or_else(|| {
+            runtime::get_key(package_key)
+                .and_then(Key::into_hash)
+                .map(ContractPackageHash::new)
+        }),
+        ApiError::MissingArgument
+    );
+
+    o_unwrap!(
+        runtime::get_key(access_key)
+            .and_then(Key::into_uref)
+            .filter(|uref| uref.access_rights() == AccessRights::READ_ADD_WRITE),
+        CommonError::InvalidAccessUref
+    );
+
+    // the host carries the named keys of the current version over to the new version
+    let (contract_hash, version) =
+        storage::add_contract_version(package_hash, entry_points, NamedKeys::new());
+    store_named_key_incremented(contract_hash.into(), &format!("{contract_key}_v{version}"));
+
+    runtime::call_versioned_contract::<()>(package_hash, None, MIGRATE_ENTRY_POINT, migrate_args);
+
+    contract_hash
+}
+
 pub trait FromNamedArg {
     fn try_get(name: &str) -> Option<Self>
     where
@@ -99,7 +158,8 @@ impl_from_named_arg!(
     casper_types::U512,
     casper_types::U128,
     casper_types::ContractHash,
-    casper_types::ContractPackageHash
+    casper_types::ContractPackageHash,
+    casper_types::account::AccountHash
 );
 
 impl<T: FromBytes> FromNamedArg for Vec<T> {
@@ -45,6 +45,8 @@ macro_rules! st_non_sync_static {
 }
 
 /// Helper macro to create a named key in the contract context, as well as accessors for it.
+///
+/// `vdict` declares a dictionary whose values are stored with a version tag, see `versioned`.
 #[macro_export]
 macro_rules! named_key {
     (@uref $name:ident, $t:ty) => {
@@ -90,7 +92,11 @@ macro_rules! named_key {
             $crate::named_key! { @uref $name, $t }
 
             pub fn try_read(key: &str) -> Option<$t> {
-                $crate::r_unwrap!(storage::dictionary_get(uref(), key), ApiError::Deserialize)
+                let bytes = $crate::contract_api::dictionary_get_bytes(uref(), key)?;
+                Some($crate::r_unwrap!(
+                    casper_types::bytesrepr::deserialize(bytes),
+                    ApiError::Deserialize
+                ))
             }
 
             pub fn read(key: &str) -> $t {
@@ -113,6 +119,54 @@ macro_rules! named_key {
         }
     };
 
+    (vdict $name:ident : $t:ty) => {
+        pub mod $name {
+            #[allow(unused)]
+            use super::*;
+            use casper_contract::contract_api::{runtime, storage};
+            use casper_types::{ApiError, URef};
+
+            $crate::named_key! { @uref $name, $t }
+
+            /// Reads an entry, upgrading it in place if it was written with an older layout.
+            pub fn try_read(key: &str) -> Option<$t> {
+                let (value, stale) = $crate::versioned::read::<$t>(uref(), key)?;
+                if stale {
+                    $crate::versioned::write(uref(), key, &value);
+                }
+
+                Some(value)
+            }
+
+            pub fn read(key: &str) -> $t {
+                $crate::o_unwrap!(try_read(key), ApiError::MissingKey)
+            }
+
+            pub fn write(key: &str, value: $t) {
+                $crate::versioned::write(uref(), key, &value);
+            }
+
+            pub fn remove(key: &str) {
+                storage::dictionary_put(uref(), key, ());
+            }
+
+            /// Rewrites the given entries in the current layout, returning how many needed it.
+            pub fn upgrade<I>(keys: I) -> u32
+            where
+                I: IntoIterator,
+                I::Item: AsRef<str>,
+            {
+                $crate::versioned::upgrade::<$t, I>(uref(), keys)
+            }
+
+            pub fn init() -> URef {
+                let uref = $crate::contract_api::new_dictionary_anon();
+                cache::set_cache(uref);
+                uref
+            }
+        }
+    };
+
     (val $name:ident : $t:ty = $init:expr) => {
         pub mod $name {
             #[allow(unused)]
@@ -145,7 +199,9 @@ macro_rules! named_key {
 
 /// Declare multiple named keys in one go.
 ///
-/// Creates an extra function to initialize all the keys at once.
+/// Creates an extra function to initialize all the keys at once, and a function suffixed with
+/// `_missing` that only initializes (and puts into the current context) the keys that don't exist
+/// yet, for use in migrations.
 #[macro_export]
 macro_rules! named_keys {
     (
@@ -164,6 +220,14 @@ macro_rules! named_keys {
         ($name::NAME.to_string(), casper_types::Key::URef( $name::init() ))
     };
 
+    (
+        @init_all
+        $v:ident
+        vdict $name:ident : $t:ty
+    ) => {
+        ($name::NAME.to_string(), casper_types::Key::URef( $name::init() ))
+    };
+
     (
         $init_all:ident ( $( $arg_i:ident : $arg_t:ty ),* $(,)? ):
         $( $k:ident $name:ident : $t:ty $(= $init:expr)? );* $(;)?
@@ -188,6 +252,86 @@ macro_rules! named_keys {
 
             v
         }
+
+        paste::paste! {
+            #[allow(unused)]
+            pub fn [< $init_all _missing >](
+                $( $arg_i : $arg_t ),*
+            ) {
+                use alloc::string::ToString;
+
+                $(
+                    if casper_contract::contract_api::runtime::get_key($name::NAME).is_none() {
+                        let (name, key) = $crate::named_keys! {
+                            @init_all
+                            v $k $name : $t $(= $init)?
+                        };
+                        casper_contract::contract_api::runtime::put_key(&name, key);
+                    }
+                )*
+            }
+        }
+    };
+}
+
+/// Declare an error enum whose variants map to their own range of `ApiError::User` codes, see
+/// `error::ErrorRange`. Fails to compile if the enum has more variants than fit in its range, or
+/// if the range belongs to another enum.
+#[macro_export]
+macro_rules! user_errors {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident in $range:ident {
+            $( $(#[$variant_meta:meta])* $variant:ident ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u16)]
+        $vis enum $name {
+            $( $(#[$variant_meta])* $variant ),*
+        }
+
+        impl $crate::error::UserError for $name {
+            const RANGE: $crate::error::ErrorRange = $crate::error::ErrorRange::$range;
+            const VARIANTS: &'static [Self] = &[$( Self::$variant ),*];
+
+            fn name(self) -> &'static str {
+                match self {
+                    $( Self::$variant => stringify!($variant) ),*
+                }
+            }
+
+            fn code(self) -> u16 {
+                Self::RANGE.base() + self as u16
+            }
+        }
+
+        const _: () = assert!(
+            <$name as $crate::error::UserError>::VARIANTS.len()
+                <= $crate::error::ERROR_RANGE_SIZE as usize,
+            concat!(stringify!($name), " has more variants than its error code range")
+        );
+
+        const _: () = assert!(
+            $crate::error::str_eq(
+                $crate::error::ErrorRange::$range.owner(),
+                stringify!($name),
+            ),
+            concat!(stringify!($name), " claims the error code range of another enum")
+        );
+
+        impl From<$name> for casper_types::ApiError {
+            fn from(error: $name) -> Self {
+                casper_types::ApiError::User($crate::error::UserError::code(error))
+            }
+        }
+
+        impl From<$name> for u16 {
+            fn from(error: $name) -> Self {
+                $crate::error::UserError::code(error)
+            }
+        }
     };
 }
 
@@ -252,6 +396,9 @@ macro_rules! serializable_structs {
 }
 
 /// Declare an entrypoint signature for a contract.
+///
+/// Markers: `public`, `contract` or `session`, and `nonreentrant` to guard the entrypoint with
+/// the lock from `reentrancy`.
 #[allow(clippy::crate_in_macro_def)]
 #[macro_export]
 macro_rules! entrypoint {
@@ -297,6 +444,24 @@ macro_rules! entrypoint {
         core::compile_error!("Missing context specifier for entrypoint")
     };
 
+    (
+        @nonreentrant nonreentrant $($attrib:ident)*
+    ) => {
+        true
+    };
+
+    (
+        @nonreentrant $_:ident $($attrib:ident)*
+    ) => {
+        $crate::entrypoint! { @nonreentrant $($attrib)* }
+    };
+
+    (
+        @nonreentrant
+    ) => {
+        false
+    };
+
     (
         $(#[$meta:meta])*
         [install] fn $name:ident
@@ -341,8 +506,17 @@ macro_rules! entrypoint {
 
             $crate::qlog!("arguments: {:?}", ($((stringify!($arg), &$arg)),*));
 
+            let nonreentrant = $crate::entrypoint! { @nonreentrant $($marker)* };
+            if nonreentrant {
+                $crate::reentrancy::enter();
+            }
+
             let result: $ret = $callback($($arg),*);
 
+            if nonreentrant {
+                $crate::reentrancy::exit();
+            }
+
             $crate::qlog!("<< {} entrypoint: {} - end", crate::NAME, stringify!($name));
 
             casper_contract::contract_api::runtime::ret(
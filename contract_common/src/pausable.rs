@@ -0,0 +1,34 @@
+//! Pause switch for a contract, gated by the [`Role::Pauser`] role.
+//!
+//! Contracts include [`init_all`] in their named keys on install, forward the [`set_paused`] and
+//! [`is_paused`] entry points, and call [`ensure_not_paused`] at the start of every entry point
+//! that should be refused while paused.
+
+use crate::{
+    access::{self, Role},
+    prelude::*,
+};
+
+named_keys! {
+    init_all():
+    val paused: bool = false;
+}
+
+/// Return whether the contract is currently paused.
+pub fn is_paused() -> bool {
+    paused::read()
+}
+
+/// Revert with [`CommonError::ContractPaused`] if the contract is paused.
+pub fn ensure_not_paused() {
+    ensure!(!is_paused(), CommonError::ContractPaused);
+}
+
+/// Pause or unpause the contract.
+///
+/// Reverts with [`CommonError::MissingRole`] unless called by a pauser.
+pub fn set_paused(value: bool) {
+    access::ensure_role(Role::Pauser);
+
+    paused::write(value);
+}
@@ -0,0 +1,24 @@
+//! Reentrancy guard for entry points that call into other contracts while moving value.
+//!
+//! Contracts include [`init_all`] in their named keys on install, and mark the guarded entry
+//! points as `nonreentrant` in `entrypoints!`. A guarded entry point reverts with
+//! [`CommonError::ReentrantCall`] if any guarded entry point of the same contract is already
+//! running higher up the call stack.
+
+use crate::prelude::*;
+
+named_keys! {
+    init_all():
+    val reentrancy_lock: bool = false;
+}
+
+/// Take the lock, or revert if it is already taken.
+pub fn enter() {
+    ensure!(!reentrancy_lock::read(), CommonError::ReentrantCall);
+    reentrancy_lock::write(true);
+}
+
+/// Release the lock. A reverted call never gets here, but it also rolls back taking the lock.
+pub fn exit() {
+    reentrancy_lock::write(false);
+}
@@ -0,0 +1,85 @@
+//! Version-tagged storage for contract state.
+//!
+//! Versioned values are stored as [`VERSION_MAGIC`], the version of the layout the value was
+//! written with, and the bytes of that layout. Values written before a type was versioned have no
+//! prefix and read as version 0, so the untagged encoding of a versioned type must never start
+//! with [`VERSION_MAGIC`].
+//!
+//! Each type lists how to decode every layout it ever had in [`Migrate::decode`], which acts as
+//! its migration registry. Old entries are upgraded to the current layout when read, or in bulk
+//! with [`upgrade`].
+
+use casper_types::{CLType, CLTyped};
+
+use crate::{contract_api::dictionary_get_bytes, prelude::*, r_unwrap};
+
+/// Prefix of version-tagged values.
+pub const VERSION_MAGIC: [u8; 4] = [0xff, 0xfe, 0xfd, 0xfc];
+
+pub trait Migrate: ToBytes + Sized {
+    /// Version of the current layout. Bump it when the layout changes, and teach `decode` to read
+    /// the previous one.
+    const VERSION: u8;
+
+    /// Decode a value written with the layout of `version`, upgrading it to the current layout.
+    fn decode(version: u8, bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error>;
+}
+
+/// A value serialized together with the version of its layout.
+pub struct Versioned<'a, T>(pub &'a T);
+
+impl<'a, T: Migrate> ToBytes for Versioned<'a, T> {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = bytesrepr::allocate_buffer(self)?;
+        result.extend_from_slice(&VERSION_MAGIC);
+        result.push(T::VERSION);
+        result.append(&mut self.0.to_bytes()?);
+        Ok(result)
+    }
+
+    fn serialized_length(&self) -> usize {
+        VERSION_MAGIC.len() + 1 + self.0.serialized_length()
+    }
+}
+
+impl<'a, T> CLTyped for Versioned<'a, T> {
+    fn cl_type() -> CLType {
+        CLType::Any
+    }
+}
+
+/// Read a versioned dictionary entry. Also returns whether it was written with an older layout.
+pub fn read<T: Migrate>(dictionary: URef, key: &str) -> Option<(T, bool)> {
+    let bytes = dictionary_get_bytes(dictionary, key)?;
+
+    let (version, bytes) = match bytes.strip_prefix(VERSION_MAGIC.as_slice()) {
+        Some(tagged) => r_unwrap!(u8::from_bytes(tagged), ApiError::Deserialize),
+        None => (0, bytes.as_slice()),
+    };
+
+    let (value, _) = r_unwrap!(T::decode(version, bytes), ApiError::Deserialize);
+    Some((value, version != T::VERSION))
+}
+
+pub fn write<T: Migrate>(dictionary: URef, key: &str, value: &T) {
+    storage::dictionary_put(dictionary, key, Versioned(value));
+}
+
+/// Rewrite the given entries in the current layout. Missing entries are skipped. Returns the
+/// number of entries that were upgraded.
+pub fn upgrade<T, I>(dictionary: URef, keys: I) -> u32
+where
+    T: Migrate,
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let mut upgraded = 0;
+    for key in keys {
+        if let Some((value, true)) = read::<T>(dictionary, key.as_ref()) {
+            write(dictionary, key.as_ref(), &value);
+            upgraded += 1;
+        }
+    }
+
+    upgraded
+}
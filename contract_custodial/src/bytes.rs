@@ -2,10 +2,14 @@ use alloc::vec;
 use alloc::vec::Vec;
 use casper_types::{
     bytesrepr::{self, FromBytes, ToBytes},
-    CLTyped, Key, U256, U512,
+    CLType, CLTyped, Key, U256, U512,
 };
+use contract_common::versioned::Migrate;
 
-use crate::state::{RoyaltyPaymentState, RoyaltyStep};
+use crate::state::{
+    MarketplaceConfig, MarketplaceMode, RecipientList, RoyaltyPaymentState, RoyaltyStep,
+    TransferExemption,
+};
 
 const ROYALTY_PAYMENT_STATE_PAID: u8 = 1;
 const ROYALTY_PAYMENT_STATE_UNPAID: u8 = 0;
@@ -131,3 +135,96 @@ impl CLTyped for RoyaltyPaymentState {
         casper_types::CLType::Any
     }
 }
+
+impl Migrate for RoyaltyPaymentState {
+    const VERSION: u8 = 1;
+
+    fn decode(version: u8, bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        match version {
+            // payments recorded before versioning have the same layout as version 1
+            0 | 1 => Self::from_bytes(bytes),
+            _ => Err(bytesrepr::Error::Formatting),
+        }
+    }
+}
+
+impl ToBytes for RecipientList {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        Ok(vec![*self as u8])
+    }
+
+    fn serialized_length(&self) -> usize {
+        1
+    }
+}
+
+impl FromBytes for RecipientList {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (tag, rem) = u8::from_bytes(bytes)?;
+        let list = RecipientList::from_u8(tag).ok_or(bytesrepr::Error::Formatting)?;
+        Ok((list, rem))
+    }
+}
+
+impl CLTyped for RecipientList {
+    fn cl_type() -> CLType {
+        CLType::U8
+    }
+}
+
+impl ToBytes for MarketplaceMode {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        Ok(vec![*self as u8])
+    }
+
+    fn serialized_length(&self) -> usize {
+        1
+    }
+}
+
+impl FromBytes for MarketplaceMode {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (tag, rem) = u8::from_bytes(bytes)?;
+        let mode = MarketplaceMode::from_u8(tag).ok_or(bytesrepr::Error::Formatting)?;
+        Ok((mode, rem))
+    }
+}
+
+impl CLTyped for MarketplaceMode {
+    fn cl_type() -> CLType {
+        CLType::U8
+    }
+}
+
+impl Migrate for MarketplaceConfig {
+    const VERSION: u8 = 1;
+
+    fn decode(version: u8, bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        match version {
+            // marketplaces used to be stored as a plain whitelist flag
+            0 => {
+                let (whitelisted, bytes) = bool::from_bytes(bytes)?;
+                let config = Self {
+                    whitelisted,
+                    ..Self::default()
+                };
+                Ok((config, bytes))
+            }
+            1 => Self::from_bytes(bytes),
+            _ => Err(bytesrepr::Error::Formatting),
+        }
+    }
+}
+
+impl Migrate for TransferExemption {
+    const VERSION: u8 = 1;
+
+    fn decode(version: u8, bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        match version {
+            // exemptions granted before versioning have the same layout as version 1, and only
+            // look tagged if their deadline happens to start with `VERSION_MAGIC`
+            0 | 1 => Self::from_bytes(bytes),
+            _ => Err(bytesrepr::Error::Formatting),
+        }
+    }
+}
@@ -4,16 +4,20 @@
 //! These are either optional or only contextually available. See the documentation of the
 //! individual methods for more information.
 
-use contract_common::{prelude::*, token::TokenIdentifier};
+use contract_common::{access::Role, prelude::*, token::TokenIdentifier};
 
-use crate::state::RoyaltyStructure;
+use crate::state::{MarketplaceConfig, MarketplaceMode, RecipientList, RoyaltyStructure};
 
 entrypoint! {
     [install] fn call(
-        whitelisted_marketplaces: Vec<ContractPackageHash>,
-        royalty_structure: RoyaltyStructure,
-        manager: Key,
-    ) -> () = crate::install
+        whitelisted_marketplaces: Option<Vec<ContractPackageHash>>,
+        royalty_structure: Option<RoyaltyStructure>,
+        manager: Option<Key>,
+        upgrade: Option<bool>,
+        package_hash: Option<ContractPackageHash>,
+        access_key: Option<String>,
+        collection: Option<ContractPackageHash>,
+    ) -> () = crate::call
 }
 
 entrypoints! {
@@ -23,7 +27,7 @@ entrypoints! {
         target_key: Key
     ) -> u8 = crate::can_transfer;
 
-    [public contract] fn pay_royalty(
+    [public contract nonreentrant] fn pay_royalty(
         token_contract: ContractPackageHash,
         token_id: TokenIdentifier,
         source_purse: URef,
@@ -33,10 +37,180 @@ entrypoints! {
         payment_amount: U512,
     ) -> () = crate::pay_royalty;
 
+    [public contract nonreentrant] fn settle_sale(
+        token_contract: ContractPackageHash,
+        token_id: TokenIdentifier,
+        source_purse: URef,
+        payer: Key,
+        source_key: Key,
+        target_key: Key,
+        seller_purse: URef,
+    ) -> U512 = crate::settle_sale;
+
+    [public contract] fn settlement_required() -> bool = crate::settlement_required;
+
+    [public contract] fn set_settlement_required(
+        required: bool,
+    ) -> () = crate::set_settlement_required;
+
+    [public contract nonreentrant] fn pay_transfer_fee(
+        token_contract: ContractPackageHash,
+        token_id: TokenIdentifier,
+        source_purse: URef,
+        target_key: Key,
+    ) -> () = crate::pay_transfer_fee;
+
+    [public contract] fn transfer_fee() -> Option<U512> = crate::transfer_fee;
+
+    [public contract] fn set_transfer_fee(fee: Option<U512>) -> () = crate::set_transfer_fee;
+
+    [public contract] fn transferable_at(
+        token_id: TokenIdentifier,
+    ) -> u64 = crate::transferable_at;
+
+    [public contract] fn transfer_lock() -> u64 = crate::transfer_lock;
+
+    [public contract] fn set_transfer_lock(until: u64) -> () = crate::set_transfer_lock;
+
+    [public contract] fn transfer_cooldown() -> u64 = crate::transfer_cooldown;
+
+    [public contract] fn set_transfer_cooldown(
+        cooldown: u64,
+    ) -> () = crate::set_transfer_cooldown;
+
+    [public contract] fn allow_transfer(
+        token_id: TokenIdentifier,
+        source_key: Key,
+        target_key: Key,
+    ) -> () = crate::allow_transfer;
+
+    [public contract] fn exempt_from_royalty(
+        account: Key,
+        until: u64,
+    ) -> () = crate::exempt_from_royalty;
+
+    [public contract] fn collection() -> Option<ContractPackageHash> = crate::collection;
+
+    [public contract] fn set_collection(
+        token_contract: ContractPackageHash,
+    ) -> () = crate::set_collection;
+
+    [public contract nonreentrant] fn claim(
+        token_id: TokenIdentifier,
+        owner: Key,
+    ) -> () = crate::claim;
+
+    [public contract] fn claimant(
+        token_id: TokenIdentifier,
+    ) -> Option<Key> = crate::claimant;
+
+    [public contract] fn set_claimant(
+        token_id: TokenIdentifier,
+        new_claimant: Option<Key>,
+    ) -> () = crate::set_claimant;
+
+    [public contract] fn delegate(
+        token_id: TokenIdentifier,
+    ) -> Option<ContractPackageHash> = crate::delegate;
+
+    [public contract] fn set_delegate(
+        token_id: TokenIdentifier,
+        new_delegate: Option<ContractPackageHash>,
+    ) -> () = crate::set_delegate;
+
+    [public contract] fn set_recipient_list(
+        list: Option<RecipientList>,
+    ) -> () = crate::set_recipient_list;
+
+    [public contract] fn recipient_list() -> Option<RecipientList> = crate::recipient_list;
+
+    [public contract] fn add_recipients(
+        list: RecipientList,
+        accounts: Vec<Key>,
+    ) -> () = crate::add_recipients;
+
+    [public contract] fn remove_recipients(
+        list: RecipientList,
+        accounts: Vec<Key>,
+    ) -> () = crate::remove_recipients;
+
+    [public contract] fn is_recipient_listed(
+        list: RecipientList,
+        account: Key,
+    ) -> bool = crate::is_recipient_listed;
+
+    [public contract] fn can_receive(account: Key) -> bool = crate::can_receive;
+
     [public contract] fn calculate_royalty(
         token_contract: ContractPackageHash,
         token_id: TokenIdentifier,
         payment_amount: U512,
     ) -> U512 = crate::calculate_royalty;
 
+    [public contract] fn floor_value(token_id: TokenIdentifier) -> U512 = crate::floor_value;
+
+    [public contract] fn set_floor_value(value: U512) -> () = crate::set_floor_value;
+
+    [public contract] fn set_token_floor_value(
+        token_id: TokenIdentifier,
+        value: Option<U512>,
+    ) -> () = crate::set_token_floor_value;
+
+    [public contract] fn set_floor_follows_last_sale(
+        enabled: bool,
+    ) -> () = crate::set_floor_follows_last_sale;
+
+    [public contract] fn marketplace_mode() -> MarketplaceMode = crate::marketplace_mode;
+
+    [public contract] fn set_marketplace_mode(
+        mode: MarketplaceMode,
+    ) -> () = crate::set_marketplace_mode;
+
+    [public contract] fn add_denied_marketplaces(
+        marketplaces: Vec<ContractPackageHash>,
+    ) -> () = crate::add_denied_marketplaces;
+
+    [public contract] fn remove_denied_marketplaces(
+        marketplaces: Vec<ContractPackageHash>,
+    ) -> () = crate::remove_denied_marketplaces;
+
+    [public contract] fn is_marketplace_denied(
+        marketplace: ContractPackageHash,
+    ) -> bool = crate::is_marketplace_denied;
+
+    [public contract] fn marketplace_config(
+        marketplace: ContractPackageHash,
+    ) -> Option<MarketplaceConfig> = crate::marketplace_config;
+
+    [public contract] fn set_marketplace_config(
+        marketplace: ContractPackageHash,
+        config: Option<MarketplaceConfig>,
+    ) -> () = crate::set_marketplace_config;
+
+    [public contract] fn migrate(
+        collection: Option<ContractPackageHash>,
+    ) -> () = crate::migrate;
+
+    [public contract] fn migrate_royalty_payments(
+        token_ids: Vec<TokenIdentifier>,
+    ) -> u32 = crate::migrate_royalty_payments;
+
+    [public contract] fn grant_role(
+        role: Role,
+        account: Key,
+    ) -> () = contract_common::access::grant_role;
+
+    [public contract] fn revoke_role(
+        role: Role,
+        account: Key,
+    ) -> () = contract_common::access::revoke_role;
+
+    [public contract] fn has_role(
+        role: Role,
+        account: Key,
+    ) -> bool = contract_common::access::has_role;
+
+    [public contract] fn set_paused(paused: bool) -> () = contract_common::pausable::set_paused;
+
+    [public contract] fn is_paused() -> bool = contract_common::pausable::is_paused;
 }
@@ -0,0 +1,16 @@
+use contract_common::{events::Event, prelude::*, token::TokenIdentifier};
+
+serializable_structs! {
+    /// A transfer went through without a royalty, on an allowance or exemption from a manager.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct RoyaltyExemptTransfer {
+        pub token_id: TokenIdentifier,
+        pub source_key: Key,
+        pub target_key: Key,
+        pub granted_by: Key,
+    }
+}
+
+impl Event for RoyaltyExemptTransfer {
+    const NAME: &'static str = "royalty_exempt_transfer";
+}
@@ -1,41 +1,103 @@
 #![no_std]
 
 use alloc::{collections::BTreeMap, string::ToString};
+use casper_types::RuntimeArgs;
 use contract_common::{
-    call_stack::CallStackElementEx, o_unwrap, prelude::*, store_named_key_incremented,
+    access::{self, Role},
+    b64,
+    call_stack::{self, CallStackElementEx},
+    o_unwrap, pausable,
+    prelude::*,
+    r_unwrap, reentrancy, store_named_key_incremented,
     token::TokenIdentifier,
+    upgrade_contract_package,
+};
+use state::{
+    MarketplaceConfig, MarketplaceMode, RecipientList, RoyaltyPaymentState, RoyaltyStructure,
+    TransferExemption,
 };
-use state::{RoyaltyPaymentState, RoyaltyStructure};
 
 extern crate alloc;
 
 mod bytes;
 pub mod entry_point;
+pub mod events;
 pub mod state;
 
 pub const NK_ACCESS_UREF: &str = "cep82_custodial_uref";
 pub const NK_CONTRACT: &str = "cep82_custodial";
+pub const NK_PACKAGE: &str = "cep82_custodial_package";
 pub const NK_ROYALTY_PURSE: &str = "royalty_purse";
 
-pub const NAME: &str = "custodial";
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(u16)]
-pub enum CustodialError {
-    MarketplaceNotWhitelisted,
-    CallerMustBeContract,
-    CallerMustBeApproved,
-    SelfTransferForbidden,
+/// Flag that enforced the whitelist, replaced by `state::marketplace_mode`.
+const NK_LEGACY_WHITELIST_ENABLED: &str = "marketplace_whitelist_enabled";
+/// Transfer fee that defaulted to zero, replaced by `state::direct_transfer_fee`.
+const NK_LEGACY_TRANSFER_FEE: &str = "transfer_fee";
 
-    SourceMustBeOwner,
-    AlreadyPaid,
+pub const NAME: &str = "custodial";
 
-    Overflow,
+/// Rebates are expressed in basis points.
+const BPS_DENOMINATOR: u64 = 10000;
+
+user_errors! {
+    pub enum CustodialError in Custodial {
+        MarketplaceNotWhitelisted,
+        CallerMustBeContract,
+        CallerMustBeApproved,
+        SelfTransferForbidden,
+
+        SourceMustBeOwner,
+        AlreadyPaid,
+
+        Overflow,
+
+        CallerMustBeAccount,
+        RecipientNotAllowed,
+        TransferLocked,
+        CollectionNotSet,
+        CallerMustBeOwner,
+        CallerMustBeDelegate,
+        SettlementRequired,
+        InvalidRebate,
+        MarketplaceDenied,
+        UnknownCollection,
+        DirectTransfersDisabled,
+        CallerMustBeClaimant,
+    }
 }
 
-impl From<CustodialError> for ApiError {
-    fn from(error: CustodialError) -> Self {
-        ApiError::User(error as u16)
+fn call(
+    whitelisted_marketplaces: Option<Vec<ContractPackageHash>>,
+    royalty_structure: Option<RoyaltyStructure>,
+    manager: Option<Key>,
+    upgrade: Option<bool>,
+    package_hash: Option<ContractPackageHash>,
+    access_key: Option<String>,
+    collection: Option<ContractPackageHash>,
+) {
+    if upgrade.unwrap_or(false) {
+        let mut migrate_args = RuntimeArgs::new();
+        if let Some(collection) = collection {
+            r_unwrap!(
+                migrate_args.insert("collection", collection),
+                ApiError::InvalidArgument
+            );
+        }
+
+        upgrade_contract_package(
+            package_hash,
+            NK_PACKAGE,
+            access_key.as_deref().unwrap_or(NK_ACCESS_UREF),
+            NK_CONTRACT,
+            entry_point::all_entrypoints().into(),
+            migrate_args,
+        );
+    } else {
+        install(
+            o_unwrap!(whitelisted_marketplaces, ApiError::MissingArgument),
+            o_unwrap!(royalty_structure, ApiError::MissingArgument),
+            o_unwrap!(manager, ApiError::MissingArgument),
+        );
     }
 }
 
@@ -44,12 +106,24 @@ fn install(
     royalty_structure: RoyaltyStructure,
     manager: Key,
 ) {
+    // an empty whitelist at install leaves the contract open to any marketplace
+    let marketplace_mode = if whitelisted_marketplaces.is_empty() {
+        MarketplaceMode::Open
+    } else {
+        MarketplaceMode::Whitelist
+    };
+
     let royalty_purse = casper_contract::contract_api::system::create_purse();
-    let mut named_keys: BTreeMap<_, _> = state::init_all(manager, royalty_structure)
-        .into_iter()
-        .collect::<_>();
+    let mut named_keys: BTreeMap<_, _> =
+        state::init_all(manager, royalty_structure, marketplace_mode)
+            .into_iter()
+            .collect::<_>();
 
     named_keys.insert(NK_ROYALTY_PURSE.to_string(), royalty_purse.into());
+    named_keys.extend(access::init_all(manager));
+    named_keys.extend(pausable::init_all());
+    named_keys.extend(reentrancy::init_all());
+    named_keys.extend(contract_common::events::init_all());
 
     let entry_points = entry_point::all_entrypoints().into();
 
@@ -61,35 +135,131 @@ fn install(
 
     store_named_key_incremented(access_uref.into(), NK_ACCESS_UREF);
     store_named_key_incremented(contract_hash.into(), NK_CONTRACT);
+    store_named_key_incremented(contract_package_hash.into(), NK_PACKAGE);
 }
 
-fn init(whitelisted_marketplaces: Vec<ContractPackageHash>) {
-    if whitelisted_marketplaces.is_empty() {
-        state::marketplace_whitelist_enabled::write(false);
+/// Bring the state of an older version of the contract up to date. Called by the installer right
+/// after an upgrade, in the same deploy, and a no-op afterwards.
+///
+/// Contracts from before `set_collection` don't know their collection, and refuse every transfer
+/// until it's set. The installer can pass it along with the upgrade as `collection`.
+fn migrate(collection: Option<ContractPackageHash>) {
+    let version = if runtime::get_key(state::schema_version::NAME).is_some() {
+        state::schema_version::read()
     } else {
-        state::marketplace_whitelist_enabled::write(true);
+        0
+    };
+
+    if version >= state::SCHEMA_VERSION {
+        return;
+    }
+
+    let manager = state::manager::read();
+    state::init_all_missing(
+        manager,
+        state::royalty_structure::read(),
+        legacy_marketplace_mode(),
+    );
+    access::init_all_missing(manager);
+    pausable::init_all_missing();
+    reentrancy::init_all_missing();
+    contract_common::events::init_all_missing();
+
+    if let Some(fee) = legacy_transfer_fee() {
+        state::direct_transfer_fee::write(Some(fee));
+    }
 
-        for marketplace in whitelisted_marketplaces {
-            state::whitelisted_marketplaces::write(&b64_cl(&marketplace), true);
+    if collection.is_some() {
+        state::collection::write(collection);
+    }
+
+    state::schema_version::write(state::SCHEMA_VERSION);
+}
+
+/// Marketplace mode of a contract from before marketplace modes, which only had a flag to enforce
+/// the whitelist. Also removes that flag.
+fn legacy_marketplace_mode() -> MarketplaceMode {
+    let whitelist_enabled = match runtime::get_key(NK_LEGACY_WHITELIST_ENABLED) {
+        Some(key) => {
+            runtime::remove_key(NK_LEGACY_WHITELIST_ENABLED);
+            let uref = o_unwrap!(key.into_uref(), ApiError::UnexpectedKeyVariant);
+            r_unwrap!(storage::read::<bool>(uref), ApiError::Deserialize).unwrap_or(false)
         }
+        None => false,
+    };
+
+    if whitelist_enabled {
+        MarketplaceMode::Whitelist
+    } else {
+        MarketplaceMode::Open
     }
 }
 
-fn pay_royalty(
-    token_contract: ContractPackageHash,
-    token_id: TokenIdentifier,
-    source_purse: URef,
-    payer: Key,
-    source_key: Key,
-    target_key: Key,
-    payment_amount: U512,
-) {
-    let royalty_purse = runtime::get_key(NK_ROYALTY_PURSE)
+/// Transfer fee of a contract from before direct transfers had to be enabled, if it was set to
+/// anything but zero. Also removes the old fee.
+fn legacy_transfer_fee() -> Option<U512> {
+    let key = runtime::get_key(NK_LEGACY_TRANSFER_FEE)?;
+    runtime::remove_key(NK_LEGACY_TRANSFER_FEE);
+    let uref = o_unwrap!(key.into_uref(), ApiError::UnexpectedKeyVariant);
+    let fee = r_unwrap!(storage::read::<U512>(uref), ApiError::Deserialize).unwrap_or_default();
+
+    (!fee.is_zero()).then_some(fee)
+}
+
+/// Rewrite the royalty payment records of the given tokens in their current layout. Records are
+/// also upgraded when they are next read, so this only saves gas on later transfers. Returns the
+/// number of records that were upgraded.
+fn migrate_royalty_payments(token_ids: Vec<TokenIdentifier>) -> u32 {
+    state::royalty_payments::upgrade(token_ids.iter().map(b64_cl))
+}
+
+fn init(whitelisted_marketplaces: Vec<ContractPackageHash>) {
+    for marketplace in whitelisted_marketplaces {
+        let config = MarketplaceConfig {
+            whitelisted: true,
+            ..MarketplaceConfig::default()
+        };
+        state::set_marketplace_config(marketplace, Some(config));
+    }
+}
+
+fn royalty_purse() -> URef {
+    runtime::get_key(NK_ROYALTY_PURSE)
         .unwrap_or_revert()
         .into_uref()
-        .unwrap_or_revert();
-    let total_royalty = calculate_royalty_inner(&token_id, payment_amount);
+        .unwrap_or_revert()
+}
+
+/// Revert if `source_key` already paid for the next transfer of `token_id`.
+fn ensure_not_paid(token_id: &TokenIdentifier, source_key: Key) {
+    let old_payment_state = state::royalty_payments::try_read(&b64_cl(token_id));
+    if let Some(RoyaltyPaymentState::Paid {
+        source_key: paid_source_key,
+        ..
+    }) = old_payment_state
+    {
+        ensure_neq!(source_key, paid_source_key, CustodialError::AlreadyPaid)
+    }
+}
 
+/// Revert unless `token_contract` is the collection this contract was pointed at with
+/// [`set_collection`]. Payments and exemptions only ever apply to tokens of that collection.
+fn ensure_collection(token_contract: ContractPackageHash) {
+    ensure_eq!(
+        token_contract,
+        o_unwrap!(state::collection::read(), CustodialError::CollectionNotSet),
+        CustodialError::UnknownCollection
+    );
+}
+
+/// Check that the calling marketplace may sell `token_id` from `source_key` to `target_key`, and
+/// that the royalty for it hasn't been paid yet. Returns the package of the marketplace.
+fn ensure_sale_allowed(
+    token_contract: ContractPackageHash,
+    token_id: &TokenIdentifier,
+    source_key: Key,
+    target_key: Key,
+) -> ContractPackageHash {
     let caller_contract_hash: Key = o_unwrap!(
         contract_common::call_stack::caller()
             .contract_hash()
@@ -103,23 +273,39 @@ fn pay_royalty(
         CustodialError::CallerMustBeContract
     );
 
-    let approved = o_unwrap!(
-        contract_common::ext::cep78::get_approved(token_contract, &token_id),
+    ensure_collection(token_contract);
+    let current_owner = contract_common::ext::cep78::owner_of(token_contract, token_id);
+
+    // the marketplace may be approved for this token, or be an operator for the owner
+    ensure!(
+        contract_common::ext::cep78::is_approved(
+            token_contract,
+            token_id,
+            current_owner,
+            caller_contract_hash
+        ),
         CustodialError::CallerMustBeApproved
     );
 
-    ensure_eq!(
-        caller_contract_hash,
-        approved,
-        CustodialError::CallerMustBeApproved
-    );
-
-    let is_whitelisted = (!state::marketplace_whitelist_enabled::read())
-        || state::is_marketplace_whitelisted(caller_contract_package);
-
-    ensure!(is_whitelisted, CustodialError::MarketplaceNotWhitelisted);
+    match state::marketplace_mode::read() {
+        MarketplaceMode::Open => {}
+        MarketplaceMode::Whitelist => ensure!(
+            state::is_marketplace_whitelisted(caller_contract_package),
+            CustodialError::MarketplaceNotWhitelisted
+        ),
+        MarketplaceMode::Denylist => ensure!(
+            !state::is_marketplace_denied(caller_contract_package),
+            CustodialError::MarketplaceDenied
+        ),
+    }
 
-    let current_owner = contract_common::ext::cep78::owner_of(token_contract, &token_id);
+    if let Some(delegate) = state::delegates::try_read(&b64_cl(token_id)) {
+        ensure_eq!(
+            caller_contract_package,
+            delegate,
+            CustodialError::CallerMustBeDelegate
+        );
+    }
 
     ensure_eq!(current_owner, source_key, CustodialError::SourceMustBeOwner);
     ensure_neq!(
@@ -127,20 +313,114 @@ fn pay_royalty(
         target_key,
         CustodialError::SelfTransferForbidden
     );
+    ensure!(can_receive(target_key), CustodialError::RecipientNotAllowed);
+    ensure!(
+        now() >= transferable_at_inner(token_id),
+        CustodialError::TransferLocked
+    );
 
-    let old_payment_state = state::royalty_payments::try_read(&b64_cl(&token_id));
-    if let Some(RoyaltyPaymentState::Paid {
-        source_key: paid_source_key,
-        ..
-    }) = old_payment_state
-    {
-        ensure_neq!(source_key, paid_source_key, CustodialError::AlreadyPaid)
+    ensure_not_paid(token_id, source_key);
+
+    caller_contract_package
+}
+
+fn pay_royalty(
+    token_contract: ContractPackageHash,
+    token_id: TokenIdentifier,
+    source_purse: URef,
+    payer: Key,
+    source_key: Key,
+    target_key: Key,
+    payment_amount: U512,
+) {
+    pausable::ensure_not_paused();
+
+    ensure!(
+        !state::settlement_required::read(),
+        CustodialError::SettlementRequired
+    );
+
+    let marketplace = ensure_sale_allowed(token_contract, &token_id, source_key, target_key);
+    let total_royalty = calculate_royalty_inner(&token_id, payment_amount, Some(marketplace));
+
+    collect_royalty(source_purse, total_royalty, marketplace);
+
+    let payment_state = RoyaltyPaymentState::Paid {
+        payer,
+        source_key,
+        amount: total_royalty,
+    };
+
+    let key = b64_cl(&token_id);
+    state::royalty_payments::write(&key, payment_state);
+    state::last_sale_prices::write(&key, payment_amount);
+}
+
+/// Move `total_royalty` out of `source_purse` into the royalty purse, less the rebate of
+/// `marketplace`, which goes to its rebate account.
+fn collect_royalty(source_purse: URef, total_royalty: U512, marketplace: ContractPackageHash) {
+    let mut kept_royalty = total_royalty;
+
+    let config = state::marketplace_config(marketplace).unwrap_or_default();
+    if let Some(rebate_account) = config.rebate_account {
+        let rebate = total_royalty
+            .checked_mul(config.rebate_bps.into())
+            .and_then(|rebate| rebate.checked_div(BPS_DENOMINATOR.into()))
+            .unwrap_or_revert_with(CustodialError::Overflow);
+
+        if !rebate.is_zero() {
+            contract_api::system::transfer_from_purse_to_account(
+                source_purse,
+                rebate_account,
+                rebate,
+                None,
+            )
+            .unwrap_or_revert();
+            kept_royalty -= rebate;
+        }
     }
 
     contract_api::system::transfer_from_purse_to_purse(
         source_purse,
-        royalty_purse,
-        total_royalty,
+        royalty_purse(),
+        kept_royalty,
+        None,
+    )
+    .unwrap_or_revert();
+}
+
+/// Settle a sale of `token_id` with everything the buyer paid, which the marketplace moved into
+/// `source_purse`. The royalty is calculated on the balance of that purse and kept, and the rest
+/// is forwarded to `seller_purse`. Returns the royalty.
+///
+/// Unlike with [`pay_royalty`], the marketplace doesn't report the sale value, so it can't
+/// under-report it either.
+#[allow(clippy::too_many_arguments)]
+fn settle_sale(
+    token_contract: ContractPackageHash,
+    token_id: TokenIdentifier,
+    source_purse: URef,
+    payer: Key,
+    source_key: Key,
+    target_key: Key,
+    seller_purse: URef,
+) -> U512 {
+    pausable::ensure_not_paused();
+
+    let marketplace = ensure_sale_allowed(token_contract, &token_id, source_key, target_key);
+
+    let payment_amount = contract_api::system::get_purse_balance(source_purse).unwrap_or_revert();
+    let total_royalty = calculate_royalty_inner(&token_id, payment_amount, Some(marketplace));
+    let seller_amount = payment_amount
+        .checked_sub(total_royalty)
+        .unwrap_or_revert_with(CustodialError::Overflow);
+
+    collect_royalty(source_purse, total_royalty, marketplace);
+
+    contract_api::system::transfer_from_purse_to_purse(
+        source_purse,
+        seller_purse,
+        seller_amount,
         None,
     )
     .unwrap_or_revert();
@@ -151,12 +431,202 @@ fn pay_royalty(
         amount: total_royalty,
     };
 
+    let key = b64_cl(&token_id);
+    state::royalty_payments::write(&key, payment_state);
+    state::last_sale_prices::write(&key, payment_amount);
+
+    total_royalty
+}
+
+fn settlement_required() -> bool {
+    state::settlement_required::read()
+}
+
+/// Make marketplaces sell through [`settle_sale`] instead of reporting the sale value to
+/// [`pay_royalty`].
+fn set_settlement_required(required: bool) {
+    access::ensure_role(Role::Manager);
+
+    state::settlement_required::write(required);
+}
+
+/// Pay the transfer fee for moving `token_id` from the calling account to `target_key` without a
+/// sale. The owner calls this from session code, which then transfers the token in the same deploy.
+fn pay_transfer_fee(
+    token_contract: ContractPackageHash,
+    token_id: TokenIdentifier,
+    source_purse: URef,
+    target_key: Key,
+) {
+    pausable::ensure_not_paused();
+
+    let fee = o_unwrap!(
+        state::direct_transfer_fee::read(),
+        CustodialError::DirectTransfersDisabled
+    );
+
+    let caller = contract_common::call_stack::caller();
+    ensure!(
+        caller.contract_package().is_none(),
+        CustodialError::CallerMustBeAccount
+    );
+    let source_key = caller.key();
+
+    ensure_collection(token_contract);
+
+    // a token with a delegate can only be moved by its delegate marketplace
+    ensure!(
+        state::delegates::try_read(&b64_cl(&token_id)).is_none(),
+        CustodialError::CallerMustBeDelegate
+    );
+
+    let current_owner = contract_common::ext::cep78::owner_of(token_contract, &token_id);
+
+    ensure_eq!(current_owner, source_key, CustodialError::SourceMustBeOwner);
+    ensure_neq!(
+        source_key,
+        target_key,
+        CustodialError::SelfTransferForbidden
+    );
+    ensure!(can_receive(target_key), CustodialError::RecipientNotAllowed);
+    ensure!(
+        now() >= transferable_at_inner(&token_id),
+        CustodialError::TransferLocked
+    );
+
+    ensure_not_paid(&token_id, source_key);
+
+    if !fee.is_zero() {
+        contract_api::system::transfer_from_purse_to_purse(
+            source_purse,
+            royalty_purse(),
+            fee,
+            None,
+        )
+        .unwrap_or_revert();
+    }
+
+    let payment_state = RoyaltyPaymentState::Paid {
+        payer: source_key,
+        source_key,
+        amount: fee,
+    };
+
     state::royalty_payments::write(&b64_cl(&token_id), payment_state);
 }
 
-fn calculate_royalty_inner(_token_id: &TokenIdentifier, payment_amount: U512) -> U512 {
-    let royalty_structure = state::royalty_structure::read();
-    royalty_structure.calculate_total_royalty(payment_amount)
+fn transfer_fee() -> Option<U512> {
+    state::direct_transfer_fee::read()
+}
+
+/// Set the fee owners pay to move a token without a sale. Direct transfers are disabled while the
+/// fee is `None`, which is the default.
+fn set_transfer_fee(fee: Option<U512>) {
+    access::ensure_role(Role::Manager);
+
+    state::direct_transfer_fee::write(fee);
+}
+
+/// Current block time in milliseconds.
+fn now() -> u64 {
+    runtime::get_blocktime().into()
+}
+
+/// Block time in milliseconds from which `token_id` can be transferred again: the end of the
+/// collection-wide lock, or of the cooldown after its last transfer, whichever comes later.
+fn transferable_at_inner(token_id: &TokenIdentifier) -> u64 {
+    let locked_until = state::transfer_locked_until::read();
+    let cooldown_until = state::last_transfers::try_read(&b64_cl(token_id))
+        .map(|last_transfer| last_transfer.saturating_add(state::transfer_cooldown::read()))
+        .unwrap_or(0);
+
+    locked_until.max(cooldown_until)
+}
+
+fn transferable_at(token_id: TokenIdentifier) -> u64 {
+    transferable_at_inner(&token_id)
+}
+
+fn transfer_lock() -> u64 {
+    state::transfer_locked_until::read()
+}
+
+/// Lock all tokens of the collection until block time `until` (in milliseconds). Pass `0` to lift
+/// the lock.
+fn set_transfer_lock(until: u64) {
+    access::ensure_role(Role::Manager);
+
+    state::transfer_locked_until::write(until);
+}
+
+fn transfer_cooldown() -> u64 {
+    state::transfer_cooldown::read()
+}
+
+/// Keep each token from being transferred again for `cooldown` milliseconds after a transfer.
+/// Applies to transfers that already happened, too.
+fn set_transfer_cooldown(cooldown: u64) {
+    access::ensure_role(Role::Manager);
+
+    state::transfer_cooldown::write(cooldown);
+}
+
+/// Calculate the royalty for a sale of `token_id` at `payment_amount`, or at its floor value if
+/// that's higher. Sales by `marketplace` use its own royalty structure, if it has one.
+fn calculate_royalty_inner(
+    token_id: &TokenIdentifier,
+    payment_amount: U512,
+    marketplace: Option<ContractPackageHash>,
+) -> U512 {
+    let royalty_structure = marketplace
+        .and_then(state::marketplace_config)
+        .and_then(|config| config.royalty_structure)
+        .unwrap_or_else(state::royalty_structure::read);
+    royalty_structure.calculate_total_royalty(payment_amount.max(floor_value_inner(token_id)))
+}
+
+/// The lowest sale value royalties of `token_id` are calculated on: its own floor if it has one,
+/// the collection-wide floor otherwise. If the floor follows sales, it's at least the price of the
+/// last sale of the token.
+fn floor_value_inner(token_id: &TokenIdentifier) -> U512 {
+    let key = b64_cl(token_id);
+    let floor = state::token_floor_values::try_read(&key).unwrap_or_else(state::floor_value::read);
+
+    if state::floor_follows_last_sale::read() {
+        floor.max(state::last_sale_prices::try_read(&key).unwrap_or_default())
+    } else {
+        floor
+    }
+}
+
+fn floor_value(token_id: TokenIdentifier) -> U512 {
+    floor_value_inner(&token_id)
+}
+
+/// Set the floor value of every token that doesn't have its own.
+fn set_floor_value(value: U512) {
+    access::ensure_role(Role::Manager);
+
+    state::floor_value::write(value);
+}
+
+/// Set the floor value of `token_id`, or fall back to the collection-wide floor if it's `None`.
+fn set_token_floor_value(token_id: TokenIdentifier, value: Option<U512>) {
+    access::ensure_role(Role::Manager);
+
+    let key = b64_cl(&token_id);
+    match value {
+        Some(value) => state::token_floor_values::write(&key, value),
+        None => state::token_floor_values::remove(&key),
+    }
+}
+
+/// Let the floor value of each token rise to the price it was last sold at. Sale prices are
+/// recorded either way, so enabling this takes earlier sales into account, too.
+fn set_floor_follows_last_sale(enabled: bool) {
+    access::ensure_role(Role::Manager);
+
+    state::floor_follows_last_sale::write(enabled);
 }
 
 fn calculate_royalty(
@@ -164,38 +634,307 @@ fn calculate_royalty(
     token_id: TokenIdentifier,
     payment_amount: U512,
 ) -> U512 {
-    calculate_royalty_inner(&token_id, payment_amount)
+    // marketplaces get a quote that takes their own royalty structure into account
+    let marketplace = call_stack::caller().contract_package();
+    calculate_royalty_inner(&token_id, payment_amount, marketplace)
+}
+
+fn marketplace_mode() -> MarketplaceMode {
+    state::marketplace_mode::read()
+}
+
+/// Switch between letting any marketplace sell, only whitelisted ones, or any but denied ones. The
+/// whitelist and the deny-list keep their entries when they aren't enforced.
+fn set_marketplace_mode(mode: MarketplaceMode) {
+    access::ensure_role(Role::Manager);
+
+    state::marketplace_mode::write(mode);
+}
+
+fn add_denied_marketplaces(marketplaces: Vec<ContractPackageHash>) {
+    access::ensure_role(Role::Manager);
+
+    for marketplace in marketplaces {
+        state::set_marketplace_denied(marketplace, true);
+    }
+}
+
+fn remove_denied_marketplaces(marketplaces: Vec<ContractPackageHash>) {
+    access::ensure_role(Role::Manager);
+
+    for marketplace in marketplaces {
+        state::set_marketplace_denied(marketplace, false);
+    }
+}
+
+fn is_marketplace_denied(marketplace: ContractPackageHash) -> bool {
+    state::is_marketplace_denied(marketplace)
+}
+
+fn marketplace_config(marketplace: ContractPackageHash) -> Option<MarketplaceConfig> {
+    state::marketplace_config(marketplace)
 }
 
-fn can_transfer(token_id: TokenIdentifier, source_key: Key, _target_key: Key) -> u8 {
+/// Configure how sales by `marketplace` are treated, or forget about it if `config` is `None`.
+fn set_marketplace_config(marketplace: ContractPackageHash, config: Option<MarketplaceConfig>) {
+    access::ensure_role(Role::Manager);
+
+    if let Some(config) = &config {
+        ensure!(
+            config.rebate_bps <= BPS_DENOMINATOR,
+            CustodialError::InvalidRebate
+        );
+    }
+
+    state::set_marketplace_config(marketplace, config);
+}
+
+fn can_transfer(token_id: TokenIdentifier, source_key: Key, target_key: Key) -> u8 {
     const PROCEED: u8 = 1;
     const DENY: u8 = 0;
 
-    let key = b64_cl(&token_id);
-    let payment_state = state::royalty_payments::read(&key);
+    pausable::ensure_not_paused();
 
-    let caller = o_unwrap!(
+    // only the collection may ask, or any contract could use up payments and exemptions
+    let collection = o_unwrap!(
         contract_common::call_stack::caller().contract_package(),
         CustodialError::CallerMustBeContract
     );
+    ensure_collection(collection);
+
+    let key = b64_cl(&token_id);
+    let payment_state =
+        state::royalty_payments::try_read(&key).unwrap_or(RoyaltyPaymentState::Unpaid);
+
+    let current_owner = contract_common::ext::cep78::owner_of(collection, &token_id);
+    if source_key != current_owner || !can_receive(target_key) {
+        return DENY;
+    }
 
-    let current_owner = contract_common::ext::cep78::owner_of(caller, &token_id);
+    let now = now();
+    if now < transferable_at_inner(&token_id) {
+        return DENY;
+    }
 
-    match payment_state {
-        RoyaltyPaymentState::Unpaid => DENY,
+    let result = match payment_state {
         RoyaltyPaymentState::Paid {
             source_key: paid_source_key,
             ..
-        } => {
-            if source_key == paid_source_key && source_key == current_owner {
-                // NB: it is ok to write `Unpaid` here, even though this technically happens *before* the transfer,
-                // because an unsuccessful transfer attempt will revert the whole deploy
-                state::royalty_payments::write(&key, RoyaltyPaymentState::Unpaid);
+        } if source_key == paid_source_key => {
+            // NB: it is ok to write `Unpaid` here, even though this technically happens *before* the transfer,
+            // because an unsuccessful transfer attempt will revert the whole deploy
+            state::royalty_payments::write(&key, RoyaltyPaymentState::Unpaid);
+
+            PROCEED
+        }
+        // exemptions are granted to owners, so they don't apply to tokens with a delegate
+        _ if state::delegates::try_read(&key).is_some() => DENY,
+        _ => match take_exemption(&token_id, source_key, target_key) {
+            Some(granted_by) => {
+                contract_common::events::emit(&events::RoyaltyExemptTransfer {
+                    token_id,
+                    source_key,
+                    target_key,
+                    granted_by,
+                });
 
                 PROCEED
-            } else {
-                DENY
             }
-        }
+            None => DENY,
+        },
+    };
+
+    if result == PROCEED {
+        state::last_transfers::write(&key, now);
+        // the delegate was chosen by the previous owner
+        state::delegates::remove(&key);
+    }
+
+    result
+}
+
+fn collection() -> Option<ContractPackageHash> {
+    state::collection::read()
+}
+
+/// Set the CEP-78 collection whose tokens are held in custody. The collection is installed after
+/// the custodial contract, since it needs it as its transfer filter, so this can't be an install
+/// argument.
+fn set_collection(token_contract: ContractPackageHash) {
+    access::ensure_role(Role::Manager);
+
+    state::collection::write(Some(token_contract));
+}
+
+/// Hand `token_id`, minted to the custodial contract, over to `owner`.
+///
+/// A manager can hand a token to anyone. Anyone else can only claim the token a manager recorded
+/// them as the claimant of with `set_claimant`, for themselves.
+fn claim(token_id: TokenIdentifier, owner: Key) {
+    pausable::ensure_not_paused();
+
+    let key = b64_cl(&token_id);
+    let caller = call_stack::caller().key();
+    if !access::has_role(Role::Manager, caller) {
+        ensure_eq!(
+            state::claimants::try_read(&key),
+            Some(caller),
+            CustodialError::CallerMustBeClaimant
+        );
+        ensure_eq!(owner, caller, CustodialError::CallerMustBeClaimant);
     }
+
+    let collection = o_unwrap!(state::collection::read(), CustodialError::CollectionNotSet);
+    let custody: Key = call_stack::current_package().into();
+
+    let current_owner = contract_common::ext::cep78::owner_of(collection, &token_id);
+    ensure_eq!(current_owner, custody, CustodialError::SourceMustBeOwner);
+    ensure!(can_receive(owner), CustodialError::RecipientNotAllowed);
+    ensure!(
+        now() >= transferable_at_inner(&token_id),
+        CustodialError::TransferLocked
+    );
+
+    // lets the transfer below through `can_transfer`, which is called back by the collection
+    let payment_state = RoyaltyPaymentState::Paid {
+        payer: call_stack::caller().key(),
+        source_key: custody,
+        amount: U512::zero(),
+    };
+    state::royalty_payments::write(&key, payment_state);
+    state::claimants::remove(&key);
+
+    contract_common::ext::cep78::transfer(collection, &token_id, custody, owner);
+}
+
+fn claimant(token_id: TokenIdentifier) -> Option<Key> {
+    state::claimants::try_read(&b64_cl(&token_id))
+}
+
+/// Record `new_claimant` as the account that may claim `token_id` out of custody, or let only
+/// managers hand it over again if it's `None`.
+fn set_claimant(token_id: TokenIdentifier, new_claimant: Option<Key>) {
+    access::ensure_role(Role::Manager);
+
+    let key = b64_cl(&token_id);
+    match new_claimant {
+        Some(new_claimant) => state::claimants::write(&key, new_claimant),
+        None => state::claimants::remove(&key),
+    }
+}
+
+fn delegate(token_id: TokenIdentifier) -> Option<ContractPackageHash> {
+    state::delegates::try_read(&b64_cl(&token_id))
+}
+
+/// Make `new_delegate` the only marketplace that can arrange transfers of `token_id`, or let any
+/// marketplace and direct transfers move it again if it's `None`. Only the owner of the token can
+/// change its delegate, and a transfer clears it.
+fn set_delegate(token_id: TokenIdentifier, new_delegate: Option<ContractPackageHash>) {
+    pausable::ensure_not_paused();
+
+    let collection = o_unwrap!(state::collection::read(), CustodialError::CollectionNotSet);
+    let current_owner = contract_common::ext::cep78::owner_of(collection, &token_id);
+    ensure_eq!(
+        call_stack::caller().key(),
+        current_owner,
+        CustodialError::CallerMustBeOwner
+    );
+
+    let key = b64_cl(&token_id);
+    match new_delegate {
+        Some(new_delegate) => state::delegates::write(&key, new_delegate),
+        None => state::delegates::remove(&key),
+    }
+}
+
+/// Return whether `account` may receive tokens under the enforced recipient list, if any.
+fn can_receive(account: Key) -> bool {
+    match state::recipient_list::read() {
+        None => true,
+        Some(RecipientList::Allow) => state::is_recipient_listed(RecipientList::Allow, account),
+        Some(RecipientList::Deny) => !state::is_recipient_listed(RecipientList::Deny, account),
+    }
+}
+
+/// Choose the recipient list to enforce, or `None` to let anyone receive tokens. Both lists keep
+/// their entries when they aren't enforced.
+fn set_recipient_list(list: Option<RecipientList>) {
+    access::ensure_role(Role::Manager);
+
+    state::recipient_list::write(list);
+}
+
+fn recipient_list() -> Option<RecipientList> {
+    state::recipient_list::read()
+}
+
+fn add_recipients(list: RecipientList, accounts: Vec<Key>) {
+    access::ensure_role(Role::Manager);
+
+    for account in accounts {
+        state::set_recipient_listed(list, account, true);
+    }
+}
+
+fn remove_recipients(list: RecipientList, accounts: Vec<Key>) {
+    access::ensure_role(Role::Manager);
+
+    for account in accounts {
+        state::set_recipient_listed(list, account, false);
+    }
+}
+
+fn is_recipient_listed(list: RecipientList, account: Key) -> bool {
+    state::is_recipient_listed(list, account)
+}
+
+/// Dictionary key of the allowance for one transfer. Hashed, since the serialized transfer is longer
+/// than a dictionary key can be.
+fn allowance_key(token_id: &TokenIdentifier, source_key: Key, target_key: Key) -> String {
+    let transfer = r_unwrap!(
+        (token_id.clone(), source_key, target_key).to_bytes(),
+        ApiError::Serialize
+    );
+
+    b64(runtime::blake2b(transfer))
+}
+
+/// Use up the allowance for this transfer, or else the exemption of `source_key`, if there is one
+/// that's still valid. Returns who granted it.
+fn take_exemption(token_id: &TokenIdentifier, source_key: Key, target_key: Key) -> Option<Key> {
+    let allowance_key = allowance_key(token_id, source_key, target_key);
+    if let Some(granted_by) = state::transfer_allowances::try_read(&allowance_key) {
+        state::transfer_allowances::remove(&allowance_key);
+        return Some(granted_by);
+    }
+
+    let exemption_key = b64_cl(&source_key);
+    let exemption = state::transfer_exemptions::try_read(&exemption_key)?;
+    state::transfer_exemptions::remove(&exemption_key);
+
+    (now() <= exemption.until).then_some(exemption.granted_by)
+}
+
+/// Let `token_id` move from `source_key` to `target_key` once without a royalty.
+fn allow_transfer(token_id: TokenIdentifier, source_key: Key, target_key: Key) {
+    access::ensure_role(Role::Manager);
+
+    let granted_by = call_stack::caller().key();
+    state::transfer_allowances::write(
+        &allowance_key(&token_id, source_key, target_key),
+        granted_by,
+    );
+}
+
+/// Let `account` make one transfer without a royalty, up to block time `until` (in milliseconds).
+/// Replaces any unused exemption of `account`.
+fn exempt_from_royalty(account: Key, until: u64) {
+    access::ensure_role(Role::Manager);
+
+    let exemption = TransferExemption {
+        until,
+        granted_by: call_stack::caller().key(),
+    };
+    state::transfer_exemptions::write(&b64_cl(&account), exemption);
 }
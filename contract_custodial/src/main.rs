@@ -14,5 +14,49 @@ forward_entrypoints! {
         calculate_royalty,
         can_transfer,
         pay_royalty,
+        settle_sale,
+        settlement_required,
+        set_settlement_required,
+        pay_transfer_fee,
+        transfer_fee,
+        set_transfer_fee,
+        transferable_at,
+        transfer_lock,
+        set_transfer_lock,
+        transfer_cooldown,
+        set_transfer_cooldown,
+        allow_transfer,
+        exempt_from_royalty,
+        collection,
+        set_collection,
+        claim,
+        claimant,
+        set_claimant,
+        delegate,
+        set_delegate,
+        set_recipient_list,
+        recipient_list,
+        add_recipients,
+        remove_recipients,
+        is_recipient_listed,
+        can_receive,
+        floor_value,
+        set_floor_value,
+        set_token_floor_value,
+        set_floor_follows_last_sale,
+        marketplace_mode,
+        set_marketplace_mode,
+        add_denied_marketplaces,
+        remove_denied_marketplaces,
+        is_marketplace_denied,
+        marketplace_config,
+        set_marketplace_config,
+        migrate,
+        migrate_royalty_payments,
+        grant_role,
+        revoke_role,
+        has_role,
+        set_paused,
+        is_paused,
     ]
 }
@@ -1,22 +1,132 @@
+use alloc::format;
+use casper_types::account::AccountHash;
 use contract_common::{prelude::*, FromNamedArg};
 use num_traits::AsPrimitive;
 
 use crate::CustodialError;
 
+/// Version of the layout of the contract state. Bump it whenever `migrate` has something new to do.
+pub const SCHEMA_VERSION: u32 = 11;
+
 named_keys! {
-    init_all(manager: Key, royalty_structure: RoyaltyStructure):
-    dict whitelisted_marketplaces: bool;
-    dict royalty_payments: RoyaltyPaymentState;
+    init_all(manager: Key, royalty_structure: RoyaltyStructure, marketplace_mode: MarketplaceMode):
+    vdict whitelisted_marketplaces: MarketplaceConfig;
+    dict denied_marketplaces: bool;
+    vdict royalty_payments: RoyaltyPaymentState;
+    dict transfer_allowances: Key;
+    vdict transfer_exemptions: TransferExemption;
+    dict recipient_lists: bool;
+    dict last_transfers: u64;
+    dict delegates: ContractPackageHash;
+    dict claimants: Key;
+    dict token_floor_values: U512;
+    dict last_sale_prices: U512;
 
-    val marketplace_whitelist_enabled: bool = false;
+    val marketplace_mode: MarketplaceMode = marketplace_mode;
     val manager: Key = manager;
     val royalty_structure: RoyaltyStructure = royalty_structure;
+    val direct_transfer_fee: Option<U512> = None;
+    val recipient_list: Option<RecipientList> = None;
+    val transfer_locked_until: u64 = 0;
+    val transfer_cooldown: u64 = 0;
+    val collection: Option<ContractPackageHash> = None;
+    val settlement_required: bool = false;
+    val floor_value: U512 = U512::zero();
+    val floor_follows_last_sale: bool = false;
+    val schema_version: u32 = SCHEMA_VERSION;
+}
+
+pub fn marketplace_config(marketplace: ContractPackageHash) -> Option<MarketplaceConfig> {
+    whitelisted_marketplaces::try_read(&b64_cl(&marketplace))
+}
+
+pub fn set_marketplace_config(marketplace: ContractPackageHash, config: Option<MarketplaceConfig>) {
+    let key = b64_cl(&marketplace);
+    match config {
+        Some(config) => whitelisted_marketplaces::write(&key, config),
+        None => whitelisted_marketplaces::remove(&key),
+    }
 }
 
 pub fn is_marketplace_whitelisted(marketplace: ContractPackageHash) -> bool {
-    whitelisted_marketplaces::try_read(&b64_cl(&marketplace)).unwrap_or(false)
+    marketplace_config(marketplace).map_or(false, |config| config.whitelisted)
+}
+
+pub fn is_marketplace_denied(marketplace: ContractPackageHash) -> bool {
+    denied_marketplaces::try_read(&b64_cl(&marketplace)).unwrap_or(false)
+}
+
+pub fn set_marketplace_denied(marketplace: ContractPackageHash, denied: bool) {
+    if denied {
+        denied_marketplaces::write(&b64_cl(&marketplace), true);
+    } else {
+        denied_marketplaces::remove(&b64_cl(&marketplace));
+    }
+}
+
+fn recipient_list_key(list: RecipientList, account: Key) -> String {
+    format!("{}{}", list as u8, b64_cl(&account))
+}
+
+pub fn is_recipient_listed(list: RecipientList, account: Key) -> bool {
+    recipient_lists::try_read(&recipient_list_key(list, account)).unwrap_or(false)
+}
+
+pub fn set_recipient_listed(list: RecipientList, account: Key, listed: bool) {
+    if listed {
+        recipient_lists::write(&recipient_list_key(list, account), true);
+    } else {
+        recipient_lists::remove(&recipient_list_key(list, account));
+    }
+}
+
+/// Which marketplaces may sell tokens of the collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MarketplaceMode {
+    /// Any marketplace.
+    Open = 0,
+    /// Only whitelisted marketplaces.
+    Whitelist = 1,
+    /// Any marketplace that isn't on the deny-list.
+    Denylist = 2,
+}
+
+impl MarketplaceMode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Open),
+            1 => Some(Self::Whitelist),
+            2 => Some(Self::Denylist),
+            _ => None,
+        }
+    }
 }
 
+impl FromNamedArg for MarketplaceMode {}
+
+/// Which accounts may receive tokens, when checked against a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RecipientList {
+    /// Only listed accounts may receive tokens.
+    Allow = 0,
+    /// Listed accounts may not receive tokens.
+    Deny = 1,
+}
+
+impl RecipientList {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Allow),
+            1 => Some(Self::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl FromNamedArg for RecipientList {}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RoyaltyPaymentState {
@@ -40,6 +150,25 @@ serializable_structs! {
     pub struct RoyaltyStructure {
         pub steps: Vec<RoyaltyStep>,
     }
+
+    /// How sales by one marketplace are treated. `royalty_structure` replaces the contract's royalty
+    /// structure for its sales, and `rebate_bps` basis points of each royalty it pays go to
+    /// `rebate_account`.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct MarketplaceConfig {
+        pub whitelisted: bool,
+        pub royalty_structure: Option<RoyaltyStructure>,
+        pub rebate_bps: u64,
+        pub rebate_account: Option<AccountHash>,
+    }
+
+    /// One royalty-free transfer from an account, granted by a manager. It can be used up to block
+    /// time `until`, in milliseconds.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct TransferExemption {
+        pub until: u64,
+        pub granted_by: Key,
+    }
 }
 
 impl RoyaltyStructure {
@@ -77,3 +206,5 @@ impl RoyaltyStructure {
 }
 
 impl FromNamedArg for RoyaltyStructure {}
+
+impl FromNamedArg for MarketplaceConfig {}
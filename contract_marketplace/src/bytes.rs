@@ -0,0 +1,160 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use casper_types::{
+    bytesrepr::{self, FromBytes, ToBytes},
+    CLType, CLTyped, ContractPackageHash, Key, U512,
+};
+
+use contract_common::{token::TokenIdentifier, versioned::Migrate};
+
+use crate::state::{BundleEntry, NftContractMetadata, OrderbookEntry, RoyaltyMode, SwapProposal};
+
+const ROYALTY_MODE_SELLER_PAYS: u8 = RoyaltyMode::SellerPays as u8;
+const ROYALTY_MODE_BUYER_PAYS: u8 = RoyaltyMode::BuyerPays as u8;
+
+impl ToBytes for RoyaltyMode {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        Ok(vec![*self as u8])
+    }
+
+    fn serialized_length(&self) -> usize {
+        1
+    }
+}
+
+impl FromBytes for RoyaltyMode {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (mode, bytes) = u8::from_bytes(bytes)?;
+        match mode {
+            ROYALTY_MODE_SELLER_PAYS => Ok((Self::SellerPays, bytes)),
+            ROYALTY_MODE_BUYER_PAYS => Ok((Self::BuyerPays, bytes)),
+            _ => Err(bytesrepr::Error::Formatting),
+        }
+    }
+}
+
+// Encoded as a plain `u8`, so that clients can pass it as such.
+impl CLTyped for RoyaltyMode {
+    fn cl_type() -> CLType {
+        CLType::U8
+    }
+}
+
+/// Layout of listings written before versioning, which had no reservation and always took the
+/// royalty out of the price.
+struct LegacyOrderbookEntry {
+    nft_contract_id: u64,
+    owner: Key,
+    token_id: TokenIdentifier,
+    price: U512,
+}
+
+impl FromBytes for LegacyOrderbookEntry {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (nft_contract_id, bytes) = u64::from_bytes(bytes)?;
+        let (owner, bytes) = Key::from_bytes(bytes)?;
+        let (token_id, bytes) = TokenIdentifier::from_bytes(bytes)?;
+        let (price, bytes) = U512::from_bytes(bytes)?;
+
+        let entry = Self {
+            nft_contract_id,
+            owner,
+            token_id,
+            price,
+        };
+        Ok((entry, bytes))
+    }
+}
+
+impl Migrate for OrderbookEntry {
+    const VERSION: u8 = 1;
+
+    fn decode(version: u8, bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        match version {
+            // the `nft_contract_id` of untagged entries never gets anywhere near `VERSION_MAGIC`
+            0 => {
+                let (legacy, bytes) = LegacyOrderbookEntry::from_bytes(bytes)?;
+                let entry = Self {
+                    nft_contract_id: legacy.nft_contract_id,
+                    owner: legacy.owner,
+                    token_id: legacy.token_id,
+                    price: legacy.price,
+                    reserved_buyer: None,
+                    royalty_mode: RoyaltyMode::SellerPays,
+                };
+                Ok((entry, bytes))
+            }
+            1 => Self::from_bytes(bytes),
+            _ => Err(bytesrepr::Error::Formatting),
+        }
+    }
+}
+
+/// Layout of collections registered before versioning, when custodial contracts couldn't be
+/// asked whether they require settlement.
+struct LegacyNftContractMetadata {
+    nft_package: ContractPackageHash,
+    custodial_package: Option<ContractPackageHash>,
+}
+
+impl FromBytes for LegacyNftContractMetadata {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (nft_package, bytes) = ContractPackageHash::from_bytes(bytes)?;
+        let (custodial_package, bytes) = Option::<ContractPackageHash>::from_bytes(bytes)?;
+
+        let metadata = Self {
+            nft_package,
+            custodial_package,
+        };
+        Ok((metadata, bytes))
+    }
+}
+
+impl Migrate for NftContractMetadata {
+    const VERSION: u8 = 1;
+
+    fn decode(version: u8, bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        match version {
+            // untagged entries start with a package hash, which starts with `VERSION_MAGIC` for
+            // about one collection in four billion
+            0 => {
+                let (legacy, bytes) = LegacyNftContractMetadata::from_bytes(bytes)?;
+                // nothing is known about the custodial contract, so it's never asked anything
+                let metadata = Self {
+                    nft_package: legacy.nft_package,
+                    custodial_package: legacy.custodial_package,
+                    legacy_custodial: true,
+                    settlement_required: false,
+                };
+                Ok((metadata, bytes))
+            }
+            1 => Self::from_bytes(bytes),
+            _ => Err(bytesrepr::Error::Formatting),
+        }
+    }
+}
+
+impl Migrate for BundleEntry {
+    const VERSION: u8 = 1;
+
+    fn decode(version: u8, bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        match version {
+            // bundles listed before versioning have the same layout as version 1, and start with
+            // a key tag, which is never `VERSION_MAGIC`
+            0 | 1 => Self::from_bytes(bytes),
+            _ => Err(bytesrepr::Error::Formatting),
+        }
+    }
+}
+
+impl Migrate for SwapProposal {
+    const VERSION: u8 = 1;
+
+    fn decode(version: u8, bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        match version {
+            // same as bundles, proposals start with a key
+            0 | 1 => Self::from_bytes(bytes),
+            _ => Err(bytesrepr::Error::Formatting),
+        }
+    }
+}
@@ -4,29 +4,142 @@
 //! These are either optional or only contextually available. See the documentation of the
 //! individual methods for more information.
 
-use casper_types::{ContractPackageHash, URef, U512};
-use contract_common::{entrypoint, entrypoints, token::TokenIdentifier};
+use alloc::{string::String, vec::Vec};
+use casper_types::{account::AccountHash, ContractPackageHash, Key, URef, U512};
+use contract_common::{access::Role, entrypoint, entrypoints, token::TokenIdentifier};
+
+use crate::state::{BundleItem, RoyaltyMode, SaleQuote};
 
 entrypoint! {
-    [install] fn call() -> () = crate::install
+    [install] fn call(
+        platform_fee_bps: Option<u64>,
+        admin: Option<Key>,
+        upgrade: Option<bool>,
+        package_hash: Option<ContractPackageHash>,
+        access_key: Option<String>,
+    ) -> () = crate::call
 }
 
 entrypoints! {
-    [public contract] fn bid(post_id: u64, source_purse: URef, amount: U512) -> () = crate::bid;
+    [public contract nonreentrant] fn bid(
+        post_id: u64,
+        source_purse: URef,
+        amount: U512,
+    ) -> () = crate::bid;
+
+    [public contract nonreentrant] fn bid_many(
+        post_ids: Vec<u64>,
+        source_purse: URef,
+        max_total: U512,
+        all_or_nothing: bool,
+    ) -> (u64, U512) = crate::bid_many;
 
     [public contract] fn post(
         nft_contract: ContractPackageHash,
         token_id: TokenIdentifier,
         target_purse: URef,
         price: U512,
+        reserved_buyer: Option<Key>,
+        royalty_mode: Option<RoyaltyMode>,
     ) -> u64 = crate::post;
 
+    [public contract] fn quote(post_id: u64) -> SaleQuote = crate::quote;
+
     [public contract] fn cancel(
         post_id: u64
     ) -> () = crate::cancel;
 
+    [public contract] fn active_listings(
+        offset: u64,
+        limit: u64,
+    ) -> Vec<u64> = crate::active_listings;
+
+    [public contract] fn listings_by_seller(
+        seller: Key,
+        offset: u64,
+        limit: u64,
+    ) -> Vec<u64> = crate::listings_by_seller;
+
+    [public contract] fn listings_by_collection(
+        nft_contract: ContractPackageHash,
+        offset: u64,
+        limit: u64,
+    ) -> Vec<u64> = crate::listings_by_collection;
+
+    [public contract] fn post_bundle(
+        items: Vec<BundleItem>,
+        target_purse: URef,
+        price: U512,
+    ) -> u64 = crate::post_bundle;
+
+    [public contract nonreentrant] fn bid_bundle(
+        bundle_id: u64,
+        source_purse: URef,
+        amount: U512,
+    ) -> () = crate::bid_bundle;
+
+    [public contract] fn cancel_bundle(
+        bundle_id: u64
+    ) -> () = crate::cancel_bundle;
+
+    [public contract] fn propose_swap(
+        counterparty: Key,
+        proposer_items: Vec<BundleItem>,
+        proposer_value: U512,
+        counterparty_items: Vec<BundleItem>,
+        counterparty_value: U512,
+        proposer_top_up: U512,
+        counterparty_top_up: U512,
+        proposer_purse: URef,
+    ) -> u64 = crate::propose_swap;
+
+    [public contract nonreentrant] fn accept_swap(
+        swap_id: u64,
+        source_purse: URef,
+    ) -> () = crate::accept_swap;
+
+    [public contract] fn cancel_swap(
+        swap_id: u64
+    ) -> () = crate::cancel_swap;
+
+    [public contract nonreentrant] fn withdraw_platform_fees(
+        target: AccountHash,
+        amount: U512,
+    ) -> () = crate::withdraw_platform_fees;
+
+    [public contract] fn set_platform_fee_bps(
+        platform_fee_bps: u64
+    ) -> () = crate::set_platform_fee_bps;
+
+    [public contract] fn migrate() -> () = crate::migrate;
+
+    [public contract] fn migrate_listings(
+        first_post_id: u64,
+        count: u64,
+    ) -> u32 = crate::migrate_listings;
+
+    [public contract] fn grant_role(
+        role: Role,
+        account: Key,
+    ) -> () = contract_common::access::grant_role;
+
+    [public contract] fn revoke_role(
+        role: Role,
+        account: Key,
+    ) -> () = contract_common::access::revoke_role;
+
+    [public contract] fn has_role(
+        role: Role,
+        account: Key,
+    ) -> bool = contract_common::access::has_role;
+
+    [public contract] fn set_paused(paused: bool) -> () = contract_common::pausable::set_paused;
+
+    [public contract] fn is_paused() -> bool = contract_common::pausable::is_paused;
+
     [public contract] fn register_cep78_contract(
         nft_package: ContractPackageHash,
         custodial_package: Option<ContractPackageHash>,
+        legacy_custodial: Option<bool>,
     ) -> () = crate::register_cep78_contract;
 }
@@ -2,49 +2,107 @@
 
 extern crate alloc;
 
+use alloc::{collections::BTreeMap, string::ToString};
+use casper_types::{account::AccountHash, RuntimeArgs};
+
+mod bytes;
 pub mod entry_point;
 pub mod state;
 
 use contract_common::{
+    access::{self, Role},
     call_stack::{self, CallStackElementEx},
-    ext, o_unwrap,
+    ext, o_unwrap, pausable,
     prelude::*,
-    r_unwrap, store_named_key_incremented,
+    r_unwrap, reentrancy, store_named_key_incremented,
     token::TokenIdentifier,
+    upgrade_contract_package,
+};
+use state::{
+    allocate, unset_target_purse_by_post_id, BundleEntry, BundleItem, ListingIndex,
+    NftContractMetadata, OrderbookEntry, RoyaltyMode, SaleQuote, SwapProposal,
 };
-use state::{unset_target_purse_by_post_id, NftContractMetadata, OrderbookEntry};
 
 use crate::state::{set_target_purse_by_post_id, target_purse_by_post_id, Counters};
 
 pub const NK_ACCESS_UREF: &str = "cep82_marketplace_uref";
 pub const NK_CONTRACT: &str = "cep82_marketplace";
+pub const NK_PACKAGE: &str = "cep82_marketplace_package";
+pub const NK_PLATFORM_FEE_PURSE: &str = "platform_fee_purse";
+
+/// Platform fees are expressed in basis points.
+const BPS_DENOMINATOR: u64 = 10000;
+
+/// Maximum number of post ids returned by a single listing query.
+const MAX_PAGE_SIZE: u64 = 100;
 
 pub const NAME: &str = "marketplace";
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(u16)]
-pub enum MarketError {
-    InvalidMethodAccess,
-    InvalidPaymentAmount,
+user_errors! {
+    pub enum MarketError in Market {
+        InvalidMethodAccess,
+        InvalidPaymentAmount,
+
+        MustBeApproved,
 
-    MustBeApproved,
+        UnsupportedNFTContract,
 
-    UnsupportedNFTContract,
+        UnknownPostId,
+        /// Never returned. Kept so the codes of the variants after it don't move.
+        UnknownTokenId,
 
-    UnknownPostId,
-    UnknownTokenId,
+        ArithmeticOverflow,
 
-    ArithmeticOverflow,
+        ReservedForAnotherBuyer,
+        StaleListing,
+
+        InvalidBundle,
+        InvalidSwap,
+        InvalidPlatformFee,
+
+        UnsupportedRoyaltyMode,
+
+        CustodialUnavailable,
+    }
 }
 
-impl From<MarketError> for ApiError {
-    fn from(error: MarketError) -> Self {
-        ApiError::User(error as u16)
+pub fn call(
+    platform_fee_bps: Option<u64>,
+    admin: Option<Key>,
+    upgrade: Option<bool>,
+    package_hash: Option<ContractPackageHash>,
+    access_key: Option<String>,
+) {
+    if upgrade.unwrap_or(false) {
+        upgrade_contract_package(
+            package_hash,
+            NK_PACKAGE,
+            access_key.as_deref().unwrap_or(NK_ACCESS_UREF),
+            NK_CONTRACT,
+            entry_point::all_entrypoints().into(),
+            RuntimeArgs::new(),
+        );
+    } else {
+        install(platform_fee_bps, admin);
     }
 }
 
-pub fn install() {
-    let named_keys = state::all_named_keys().into_iter().collect::<_>();
+pub fn install(platform_fee_bps: Option<u64>, admin: Option<Key>) {
+    let platform_fee_bps = platform_fee_bps.unwrap_or(0);
+    ensure_valid_platform_fee(platform_fee_bps);
+
+    let platform_fee_purse = casper_contract::contract_api::system::create_purse();
+    let mut named_keys: BTreeMap<_, _> = state::all_named_keys(platform_fee_bps)
+        .into_iter()
+        .collect::<_>();
+
+    named_keys.insert(NK_PLATFORM_FEE_PURSE.to_string(), platform_fee_purse.into());
+
+    let admin = admin.unwrap_or_else(|| Key::Account(runtime::get_caller()));
+    named_keys.extend(access::init_all(admin));
+    named_keys.extend(pausable::init_all());
+    named_keys.extend(reentrancy::init_all());
+
     let entry_points = entry_point::all_entrypoints().into();
 
     let (contract_package_hash, access_uref) = storage::create_contract_package_at_hash();
@@ -53,110 +111,603 @@ pub fn install() {
 
     store_named_key_incremented(access_uref.into(), NK_ACCESS_UREF);
     store_named_key_incremented(contract_hash.into(), NK_CONTRACT);
+    store_named_key_incremented(contract_package_hash.into(), NK_PACKAGE);
 }
 
-pub fn bid(post_id: u64, source_purse: URef, amount: U512) {
-    let entry = OrderbookEntry::by_id(post_id);
-    let bidder = call_stack::caller().key();
-    let owner = entry.owner;
+/// Bring the state of an older version of the contract up to date. Called by the installer right
+/// after an upgrade, in the same deploy, and a no-op afterwards.
+pub fn migrate() {
+    let version = if runtime::get_key(state::schema_version::NAME).is_some() {
+        state::schema_version::read()
+    } else {
+        0
+    };
 
-    if amount < entry.price {
-        revert(MarketError::InvalidPaymentAmount);
+    if version >= state::SCHEMA_VERSION {
+        return;
     }
 
-    let nft_contract = NftContractMetadata::by_id(entry.nft_contract_id);
+    if runtime::get_key(NK_PLATFORM_FEE_PURSE).is_none() {
+        let platform_fee_purse = casper_contract::contract_api::system::create_purse();
+        runtime::put_key(NK_PLATFORM_FEE_PURSE, platform_fee_purse.into());
+    }
 
-    if let Some(custodial_package) = nft_contract.custodial_package {
-        let royalty_amount = ext::cep82::custodial::calculate_royalty(
-            custodial_package,
-            nft_contract.nft_package,
-            &entry.token_id,
-            entry.price,
-        );
+    state::all_named_keys_missing(0);
+    // the installer is the one running the migration
+    access::init_all_missing(call_stack::caller().key());
+    pausable::init_all_missing();
+    reentrancy::init_all_missing();
 
-        let royalty_purse = casper_contract::contract_api::system::create_purse();
-        let remaining_amount = amount
-            .checked_sub(royalty_amount)
-            .unwrap_or_revert_with(MarketError::ArithmeticOverflow);
+    state::schema_version::write(state::SCHEMA_VERSION);
+}
 
-        r_unwrap!(
-            casper_contract::contract_api::system::transfer_from_purse_to_purse(
-                source_purse,
-                royalty_purse,
-                royalty_amount,
-                None,
-            )
-        );
+/// Rewrite up to `count` listings starting at `first_post_id` in their current layout, add them
+/// to the listing indexes, which older listings predate, and file them under their collection and
+/// token id. Listings are also upgraded when they are next read, but only this makes them show up
+/// in listing queries. Returns the number of listings that were upgraded.
+pub fn migrate_listings(first_post_id: u64, count: u64) -> u32 {
+    let end = first_post_id
+        .saturating_add(count.min(MAX_PAGE_SIZE))
+        .min(Counters::read().post_id);
+
+    let upgraded = OrderbookEntry::upgrade(first_post_id..end);
+    for post_id in first_post_id..end {
+        if let Some(entry) = OrderbookEntry::try_by_id(post_id) {
+            entry.index(post_id);
+
+            if state::legacy_post_id_by_token_id(&entry.token_id) == Some(post_id) {
+                state::unset_legacy_post_id_by_token_id(&entry.token_id);
+                state::set_post_id_by_token_id(
+                    entry.nft_contract_id,
+                    &entry.token_id,
+                    Some(post_id),
+                );
+            }
+        }
+    }
+
+    upgraded
+}
+
+/// A listing, together with the royalty its sale currently incurs.
+///
+/// Both [`quote`] and [`bid`] go through this, so a quote always matches the settlement.
+struct Sale {
+    post_id: u64,
+    entry: OrderbookEntry,
+    nft_contract: NftContractMetadata,
+    /// The custodial contract, if it settles the sale itself.
+    settling_custodial: Option<ContractPackageHash>,
+    royalty: U512,
+}
+
+impl Sale {
+    /// Load `post_id` and calculate the royalty for selling it at the listed price.
+    ///
+    /// A settling custodial contract calculates the royalty on everything it's handed, which for
+    /// the listings it can settle is the listed price.
+    fn load(post_id: u64) -> Result<Self, MarketError> {
+        let entry = OrderbookEntry::try_by_id(post_id).ok_or(MarketError::UnknownPostId)?;
+        let nft_contract = NftContractMetadata::try_by_id(entry.nft_contract_id)
+            .ok_or(MarketError::UnsupportedNFTContract)?;
+        let settling_custodial = settling_custodial(&nft_contract);
+        let royalty = royalty_due(&nft_contract, &entry.token_id, entry.price);
+
+        Ok(Self {
+            post_id,
+            entry,
+            nft_contract,
+            settling_custodial,
+            royalty,
+        })
+    }
+
+    /// Check that `post_id` can currently be bought by `bidder`.
+    ///
+    /// Doesn't revert, so that [`bid_many`] can skip listings that can't be filled.
+    fn prepare(post_id: u64, bidder: Key) -> Result<Self, MarketError> {
+        let sale = Self::load(post_id)?;
+        let entry = &sale.entry;
+
+        if let Some(reserved_buyer) = entry.reserved_buyer {
+            if bidder != reserved_buyer {
+                return Err(MarketError::ReservedForAnotherBuyer);
+            }
+        }
+
+        // the seller might have moved the token or revoked the approval since posting
+        let nft_package = sale.nft_contract.nft_package;
+        let owner = ext::cep78::owner_of(nft_package, &entry.token_id);
+        let this: Key = call_stack::current_contract().into();
+        if owner != entry.owner
+            || !ext::cep78::is_approved(nft_package, &entry.token_id, owner, this)
+        {
+            return Err(MarketError::StaleListing);
+        }
+
+        Ok(sale)
+    }
+
+    /// Check that the custodial contract will let the sale through: that it isn't paused and that
+    /// the token isn't locked or cooling down. The custodial contract would revert the whole call
+    /// otherwise, so [`bid_many`] asks first. Custodial contracts of the first release can't be
+    /// asked.
+    fn ensure_custodial_ready(&self) -> Result<(), MarketError> {
+        let custodial_package = match self.nft_contract.custodial_package {
+            Some(custodial_package) if !self.nft_contract.legacy_custodial => custodial_package,
+            _ => return Ok(()),
+        };
+
+        let now: u64 = runtime::get_blocktime().into();
+        if ext::cep82::custodial::is_paused(custodial_package)
+            || ext::cep82::custodial::transferable_at(custodial_package, &self.entry.token_id) > now
+        {
+            return Err(MarketError::CustodialUnavailable);
+        }
+
+        Ok(())
+    }
+
+    /// Break the sale down into what the buyer pays and where the money goes.
+    fn quote(&self) -> Result<SaleQuote, MarketError> {
+        let price = self.entry.price;
+        let royalty = self.royalty;
+        let platform_fee = platform_fee(price)?;
+
+        let total_due = match (self.entry.royalty_mode, self.settling_custodial) {
+            (RoyaltyMode::SellerPays, _) => price,
+            // the royalty would be calculated on itself, too
+            (RoyaltyMode::BuyerPays, Some(_)) => return Err(MarketError::UnsupportedRoyaltyMode),
+            (RoyaltyMode::BuyerPays, None) => price
+                .checked_add(royalty)
+                .ok_or(MarketError::ArithmeticOverflow)?,
+        };
+
+        let seller_proceeds = total_due
+            .checked_sub(royalty)
+            .and_then(|amount| amount.checked_sub(platform_fee))
+            .ok_or(MarketError::ArithmeticOverflow)?;
+
+        Ok(SaleQuote {
+            price,
+            royalty,
+            platform_fee,
+            seller_proceeds,
+            total_due,
+        })
+    }
+
+    /// Pay for the listing out of `source_purse`, collect the royalty if the collection is
+    /// custodial and transfer the token to `bidder`.
+    ///
+    /// Only the total due is taken out of `source_purse`, `amount` is the most the bidder agreed
+    /// to pay.
+    fn settle(self, bidder: Key, source_purse: URef, amount: U512) {
+        let quote = self.quote().unwrap_or_revert();
+        ensure!(amount >= quote.total_due, MarketError::InvalidPaymentAmount);
+
+        let Self {
+            post_id,
+            entry,
+            nft_contract,
+            settling_custodial,
+            ..
+        } = self;
 
         let target_purse = o_unwrap!(target_purse_by_post_id(post_id), MarketError::UnknownPostId);
-        r_unwrap!(
-            casper_contract::contract_api::system::transfer_from_purse_to_purse(
-                source_purse,
-                target_purse,
-                remaining_amount,
-                None,
-            )
-        );
 
-        ext::cep82::custodial::pay_royalty(
-            custodial_package,
+        match settling_custodial {
+            Some(custodial_package) => {
+                settle_through_custodial(
+                    custodial_package,
+                    &nft_contract,
+                    &entry,
+                    bidder,
+                    source_purse,
+                    quote.total_due,
+                    quote.platform_fee,
+                    target_purse,
+                );
+            }
+            None => {
+                pay_royalty(
+                    &nft_contract,
+                    &entry.token_id,
+                    source_purse,
+                    quote.royalty,
+                    bidder,
+                    entry.owner,
+                    entry.reserved_buyer.unwrap_or(bidder),
+                    entry.price,
+                );
+
+                pay_platform_fee(source_purse, quote.platform_fee);
+
+                r_unwrap!(
+                    casper_contract::contract_api::system::transfer_from_purse_to_purse(
+                        source_purse,
+                        target_purse,
+                        quote.seller_proceeds,
+                        None,
+                    )
+                );
+            }
+        }
+
+        ext::cep78::transfer(
             nft_contract.nft_package,
             &entry.token_id,
-            royalty_purse,
+            entry.owner,
             bidder,
-            owner,
-            bidder,
-            entry.price,
         );
+
+        remove_listing(post_id, &entry);
     }
+}
 
-    ext::cep78::transfer(
+/// Hand `amount` to a custodial contract that requires settlement, which keeps the royalty and
+/// sends the rest back. The seller then receives that minus the platform fee.
+///
+/// The custodial contract calculates the royalty on everything it's handed, so only listings
+/// where the seller pays the royalty can be settled this way. [`Sale::quote`] refuses the others.
+#[allow(clippy::too_many_arguments)]
+fn settle_through_custodial(
+    custodial_package: ContractPackageHash,
+    nft_contract: &NftContractMetadata,
+    entry: &OrderbookEntry,
+    bidder: Key,
+    source_purse: URef,
+    amount: U512,
+    platform_fee: U512,
+    target_purse: URef,
+) {
+    let sale_purse = casper_contract::contract_api::system::create_purse();
+    r_unwrap!(
+        casper_contract::contract_api::system::transfer_from_purse_to_purse(
+            source_purse,
+            sale_purse,
+            amount,
+            None,
+        )
+    );
+
+    let proceeds_purse = casper_contract::contract_api::system::create_purse();
+    ext::cep82::custodial::settle_sale(
+        custodial_package,
         nft_contract.nft_package,
         &entry.token_id,
-        entry.owner,
+        sale_purse,
         bidder,
+        entry.owner,
+        entry.reserved_buyer.unwrap_or(bidder),
+        proceeds_purse,
+    );
+
+    pay_platform_fee(proceeds_purse, platform_fee);
+
+    let proceeds =
+        casper_contract::contract_api::system::get_purse_balance(proceeds_purse).unwrap_or_revert();
+    r_unwrap!(
+        casper_contract::contract_api::system::transfer_from_purse_to_purse(
+            proceeds_purse,
+            target_purse,
+            proceeds,
+            None,
+        )
     );
+}
+
+fn platform_fee_purse() -> URef {
+    runtime::get_key(NK_PLATFORM_FEE_PURSE)
+        .unwrap_or_revert()
+        .into_uref()
+        .unwrap_or_revert()
+}
+
+/// Move `platform_fee` out of `source_purse` into the platform fee purse.
+fn pay_platform_fee(source_purse: URef, platform_fee: U512) {
+    if platform_fee.is_zero() {
+        return;
+    }
+
+    r_unwrap!(
+        casper_contract::contract_api::system::transfer_from_purse_to_purse(
+            source_purse,
+            platform_fee_purse(),
+            platform_fee,
+            None,
+        )
+    );
+}
+
+/// Send `amount` of the collected platform fees to `target`.
+pub fn withdraw_platform_fees(target: AccountHash, amount: U512) {
+    access::ensure_role(Role::Treasurer);
 
+    r_unwrap!(
+        casper_contract::contract_api::system::transfer_from_purse_to_account(
+            platform_fee_purse(),
+            target,
+            amount,
+            None,
+        )
+    );
+}
+
+fn ensure_valid_platform_fee(platform_fee_bps: u64) {
+    ensure!(
+        platform_fee_bps <= BPS_DENOMINATOR,
+        MarketError::InvalidPlatformFee
+    );
+}
+
+/// Change the platform fee taken from sales. Applies to existing listings, too.
+pub fn set_platform_fee_bps(platform_fee_bps: u64) {
+    access::ensure_role(Role::Manager);
+    ensure_valid_platform_fee(platform_fee_bps);
+
+    state::platform_fee_bps::write(platform_fee_bps);
+}
+
+/// Remove a listing and everything that refers to it.
+fn remove_listing(post_id: u64, entry: &OrderbookEntry) {
     unset_target_purse_by_post_id(post_id);
+    if state::post_id_by_token_id(entry.nft_contract_id, &entry.token_id) == Some(post_id) {
+        state::set_post_id_by_token_id(entry.nft_contract_id, &entry.token_id, None);
+    }
+    if state::legacy_post_id_by_token_id(&entry.token_id) == Some(post_id) {
+        state::unset_legacy_post_id_by_token_id(&entry.token_id);
+    }
+    entry.unindex(post_id);
     OrderbookEntry::remove(post_id);
 }
 
-pub fn post(
-    nft_contract: ContractPackageHash,
-    token_id: TokenIdentifier,
-    target_purse: URef,
-    price: U512,
-) -> u64 {
-    let caller = call_stack::caller().key();
+/// Remove the single listing of `token_id`, if it has one, once the token was sold in a bundle or
+/// a swap.
+fn remove_single_listing(nft_contract_id: u64, token_id: &TokenIdentifier) {
+    let listing = state::post_id_by_token_id(nft_contract_id, token_id)
+        .or_else(|| state::legacy_post_id_by_token_id(token_id))
+        .and_then(|post_id| OrderbookEntry::try_by_id(post_id).map(|entry| (post_id, entry)));
 
-    let (nft_contract_id, _) = NftContractMetadata::by_package_hash(nft_contract);
+    // listings that weren't migrated yet are only filed by token id, which collections may share
+    if let Some((post_id, entry)) = listing {
+        if entry.nft_contract_id == nft_contract_id && entry.token_id == *token_id {
+            remove_listing(post_id, &entry);
+        }
+    }
+}
 
-    let approved = o_unwrap!(
-        ext::cep78::get_approved(nft_contract, &token_id),
-        MarketError::MustBeApproved
+pub fn bid(post_id: u64, source_purse: URef, amount: U512) {
+    pausable::ensure_not_paused();
+
+    let bidder = call_stack::caller().key();
+    let sale = Sale::prepare(post_id, bidder).unwrap_or_revert();
+
+    sale.settle(bidder, source_purse, amount);
+}
+
+/// Return the breakdown of a sale of `post_id`, including the amount the buyer has to send.
+pub fn quote(post_id: u64) -> SaleQuote {
+    let sale = Sale::load(post_id).unwrap_or_revert();
+    sale.quote().unwrap_or_revert()
+}
+
+/// Buy several listings in one go, paying each listing's total due out of `source_purse`.
+///
+/// With `all_or_nothing` set, any listing that can't be filled (or that would exceed `max_total`,
+/// or what's left in `source_purse`) reverts the whole call. Otherwise such listings are skipped.
+/// Listings whose custodial contract would refuse the sale count as ones that can't be filled.
+///
+/// Returns the number of listings bought and the total amount spent.
+pub fn bid_many(
+    post_ids: Vec<u64>,
+    source_purse: URef,
+    max_total: U512,
+    all_or_nothing: bool,
+) -> (u64, U512) {
+    pausable::ensure_not_paused();
+
+    let bidder = call_stack::caller().key();
+    let mut count = 0u64;
+    let mut total = U512::zero();
+
+    for post_id in post_ids {
+        let sale = Sale::prepare(post_id, bidder).and_then(|sale| {
+            let total_due = sale.quote()?.total_due;
+            let new_total = total
+                .checked_add(total_due)
+                .ok_or(MarketError::ArithmeticOverflow)?;
+
+            let balance = casper_contract::contract_api::system::get_purse_balance(source_purse)
+                .unwrap_or_revert();
+            if new_total > max_total || balance < total_due {
+                return Err(MarketError::InvalidPaymentAmount);
+            }
+
+            sale.ensure_custodial_ready()?;
+            Ok((sale, total_due, new_total))
+        });
+
+        match sale {
+            Ok((sale, total_due, new_total)) => {
+                sale.settle(bidder, source_purse, total_due);
+
+                count += 1;
+                total = new_total;
+            }
+            Err(error) if all_or_nothing => revert(error),
+            Err(_) => continue,
+        }
+    }
+
+    (count, total)
+}
+
+/// Calculate the platform fee taken from a sale at `price`.
+fn platform_fee(price: U512) -> Result<U512, MarketError> {
+    let platform_fee_bps = state::platform_fee_bps::read();
+
+    price
+        .checked_mul(platform_fee_bps.into())
+        .and_then(|fee| fee.checked_div(BPS_DENOMINATOR.into()))
+        .ok_or(MarketError::ArithmeticOverflow)
+}
+
+/// The custodial contract of `nft_contract`, if it required sales to be settled through it when
+/// the collection was registered.
+fn settling_custodial(nft_contract: &NftContractMetadata) -> Option<ContractPackageHash> {
+    nft_contract
+        .custodial_package
+        .filter(|_| nft_contract.settlement_required)
+}
+
+/// Calculate the royalty for selling `token_id` at `payment_amount`, if the collection has a
+/// custodial contract attached.
+fn royalty_due(
+    nft_contract: &NftContractMetadata,
+    token_id: &TokenIdentifier,
+    payment_amount: U512,
+) -> U512 {
+    match nft_contract.custodial_package {
+        Some(custodial_package) => ext::cep82::custodial::calculate_royalty(
+            custodial_package,
+            nft_contract.nft_package,
+            token_id,
+            payment_amount,
+        ),
+        None => U512::zero(),
+    }
+}
+
+/// Pay `royalty_amount` for a sale of `token_id` out of `source_purse`, if the collection has a
+/// custodial contract attached.
+#[allow(clippy::too_many_arguments)]
+fn pay_royalty(
+    nft_contract: &NftContractMetadata,
+    token_id: &TokenIdentifier,
+    source_purse: URef,
+    royalty_amount: U512,
+    payer: Key,
+    source_key: Key,
+    target_key: Key,
+    payment_amount: U512,
+) {
+    let custodial_package = match nft_contract.custodial_package {
+        Some(custodial_package) => custodial_package,
+        None => return,
+    };
+
+    let royalty_purse = casper_contract::contract_api::system::create_purse();
+    r_unwrap!(
+        casper_contract::contract_api::system::transfer_from_purse_to_purse(
+            source_purse,
+            royalty_purse,
+            royalty_amount,
+            None,
+        )
     );
 
+    ext::cep82::custodial::pay_royalty(
+        custodial_package,
+        nft_contract.nft_package,
+        token_id,
+        royalty_purse,
+        payer,
+        source_key,
+        target_key,
+        payment_amount,
+    );
+}
+
+/// Calculate and pay the royalty for a sale of `token_id` out of `source_purse`.
+///
+/// Returns the amount of royalty paid.
+fn collect_royalty(
+    nft_contract: &NftContractMetadata,
+    token_id: &TokenIdentifier,
+    source_purse: URef,
+    payer: Key,
+    source_key: Key,
+    target_key: Key,
+    payment_amount: U512,
+) -> U512 {
+    let royalty_amount = royalty_due(nft_contract, token_id, payment_amount);
+
+    pay_royalty(
+        nft_contract,
+        token_id,
+        source_purse,
+        royalty_amount,
+        payer,
+        source_key,
+        target_key,
+        payment_amount,
+    );
+
+    royalty_amount
+}
+
+/// Check that `caller` owns `token_id` and has approved the marketplace to transfer it, either for
+/// this token or as an operator.
+fn ensure_listable(nft_contract: ContractPackageHash, token_id: &TokenIdentifier, caller: Key) {
+    let owner = ext::cep78::owner_of(nft_contract, token_id);
+
     let this: Key = call_stack::current_contract().into();
-    ensure_eq!(approved, this, MarketError::MustBeApproved);
+    ensure!(
+        ext::cep78::is_approved(nft_contract, token_id, owner, this),
+        MarketError::MustBeApproved
+    );
 
-    let owner = ext::cep78::owner_of(nft_contract, &token_id);
     ensure_eq!(owner, caller, MarketError::InvalidMethodAccess);
+}
 
+fn next_post_id() -> u64 {
     let mut counters = Counters::read();
     let post_id = counters.post_id;
     counters.post_id += 1;
     counters.write();
-    state::set_post_id_by_token_id(&token_id, Some(post_id));
+    post_id
+}
+
+pub fn post(
+    nft_contract: ContractPackageHash,
+    token_id: TokenIdentifier,
+    target_purse: URef,
+    price: U512,
+    reserved_buyer: Option<Key>,
+    royalty_mode: Option<RoyaltyMode>,
+) -> u64 {
+    pausable::ensure_not_paused();
+
+    let caller = call_stack::caller().key();
+
+    let (nft_contract_id, metadata) = NftContractMetadata::by_package_hash(nft_contract);
+    ensure_listable(nft_contract, &token_id, caller);
+
+    // a settling custodial contract takes the royalty out of what the buyer pays
+    let royalty_mode = royalty_mode.unwrap_or(RoyaltyMode::SellerPays);
+    if royalty_mode == RoyaltyMode::BuyerPays {
+        ensure!(
+            settling_custodial(&metadata).is_none(),
+            MarketError::UnsupportedRoyaltyMode
+        );
+    }
+
+    let post_id = next_post_id();
+    state::set_post_id_by_token_id(nft_contract_id, &token_id, Some(post_id));
 
     let entry = OrderbookEntry {
-        owner,
+        owner: caller,
         nft_contract_id,
         token_id,
         price,
+        reserved_buyer,
+        royalty_mode,
     };
 
     set_target_purse_by_post_id(post_id, target_purse);
 
+    entry.index(post_id);
     entry.write(post_id);
 
     post_id
@@ -170,22 +721,331 @@ pub fn cancel(post_id: u64) {
         revert(MarketError::InvalidMethodAccess);
     }
 
-    state::set_post_id_by_token_id(&entry.token_id, None);
-    OrderbookEntry::remove(post_id);
+    remove_listing(post_id, &entry);
+}
+
+/// Return up to `limit` active post ids, starting from `offset`.
+pub fn active_listings(offset: u64, limit: u64) -> Vec<u64> {
+    ListingIndex::Active.page(offset, limit.min(MAX_PAGE_SIZE))
+}
+
+/// Return up to `limit` post ids of active listings by `seller`, starting from `offset`.
+pub fn listings_by_seller(seller: Key, offset: u64, limit: u64) -> Vec<u64> {
+    ListingIndex::BySeller(seller).page(offset, limit.min(MAX_PAGE_SIZE))
+}
+
+/// Return up to `limit` post ids of active listings in `nft_contract`, starting from `offset`.
+pub fn listings_by_collection(
+    nft_contract: ContractPackageHash,
+    offset: u64,
+    limit: u64,
+) -> Vec<u64> {
+    let (nft_contract_id, _) = NftContractMetadata::by_package_hash(nft_contract);
+    ListingIndex::ByCollection(nft_contract_id).page(offset, limit.min(MAX_PAGE_SIZE))
+}
+
+/// Check that a list of items is non-empty, free of duplicates and only references supported
+/// collections.
+fn ensure_valid_items(items: &[BundleItem]) {
+    ensure!(!items.is_empty(), MarketError::InvalidBundle);
+
+    for (i, item) in items.iter().enumerate() {
+        let is_duplicate = items[..i].iter().any(|other| {
+            other.nft_contract == item.nft_contract && other.token_id == item.token_id
+        });
+        ensure!(!is_duplicate, MarketError::InvalidBundle);
+
+        NftContractMetadata::by_package_hash(item.nft_contract);
+    }
+}
+
+/// List several tokens for sale together, at a single `price`.
+///
+/// The price is split equally across the items, and each item's share is what its custodial
+/// contract sees as the sale value. The seller has no say in the split, so it can't be used to
+/// move value away from the items that carry a royalty. A custodial contract with a floor value
+/// still calculates the royalty on at least that.
+pub fn post_bundle(items: Vec<BundleItem>, target_purse: URef, price: U512) -> u64 {
+    pausable::ensure_not_paused();
+
+    let caller = call_stack::caller().key();
+
+    ensure_valid_items(&items);
+    for item in &items {
+        ensure_listable(item.nft_contract, &item.token_id, caller);
+    }
+
+    let bundle_id = next_post_id();
+
+    let entry = BundleEntry {
+        owner: caller,
+        items,
+        price,
+    };
+
+    set_target_purse_by_post_id(bundle_id, target_purse);
+
+    entry.write(bundle_id);
+
+    bundle_id
+}
+
+/// Buy a bundle. Either every item is transferred to the bidder, or the whole call reverts.
+///
+/// The platform fee is taken on the bundle price, like on the price of a single listing.
+pub fn bid_bundle(bundle_id: u64, source_purse: URef, amount: U512) {
+    pausable::ensure_not_paused();
+
+    let bidder = call_stack::caller().key();
+    let entry = BundleEntry::by_id(bundle_id);
+
+    ensure!(amount >= entry.price, MarketError::InvalidPaymentAmount);
+
+    let target_purse = o_unwrap!(
+        target_purse_by_post_id(bundle_id),
+        MarketError::UnknownPostId
+    );
+
+    let platform_fee = platform_fee(entry.price).unwrap_or_revert();
+    pay_platform_fee(source_purse, platform_fee);
+
+    let mut remaining_amount = amount
+        .checked_sub(platform_fee)
+        .unwrap_or_revert_with(MarketError::ArithmeticOverflow);
+    for (item, allocation) in entry.items.iter().zip(entry.allocations()) {
+        let (nft_contract_id, nft_contract) =
+            NftContractMetadata::by_package_hash(item.nft_contract);
+
+        let royalty_amount = collect_royalty(
+            &nft_contract,
+            &item.token_id,
+            source_purse,
+            bidder,
+            entry.owner,
+            bidder,
+            allocation,
+        );
+        remaining_amount = remaining_amount
+            .checked_sub(royalty_amount)
+            .unwrap_or_revert_with(MarketError::ArithmeticOverflow);
+
+        ext::cep78::transfer(item.nft_contract, &item.token_id, entry.owner, bidder);
+        remove_single_listing(nft_contract_id, &item.token_id);
+    }
+
+    r_unwrap!(
+        casper_contract::contract_api::system::transfer_from_purse_to_purse(
+            source_purse,
+            target_purse,
+            remaining_amount,
+            None,
+        )
+    );
+
+    unset_target_purse_by_post_id(bundle_id);
+    BundleEntry::remove(bundle_id);
+}
+
+pub fn cancel_bundle(bundle_id: u64) {
+    let caller = call_stack::caller().key();
+    let entry = BundleEntry::by_id(bundle_id);
+
+    ensure_eq!(entry.owner, caller, MarketError::InvalidMethodAccess);
+
+    unset_target_purse_by_post_id(bundle_id);
+    BundleEntry::remove(bundle_id);
+}
+
+/// Propose a swap of the caller's `proposer_items` for the `counterparty`'s `counterparty_items`.
+///
+/// Each side declares the value of the items it gives away, which is split equally across those
+/// items and reported to their custodial contracts. Each side pays the royalties for the items it
+/// gives away, and the platform fee on their value. Optionally, one of the sides adds a CSPR
+/// top-up, paid to the other side.
+///
+/// The `proposer_purse` is used to pay the proposer's royalties, fee and top-up, and receives the
+/// counterparty's top-up, once the swap is accepted.
+#[allow(clippy::too_many_arguments)]
+pub fn propose_swap(
+    counterparty: Key,
+    proposer_items: Vec<BundleItem>,
+    proposer_value: U512,
+    counterparty_items: Vec<BundleItem>,
+    counterparty_value: U512,
+    proposer_top_up: U512,
+    counterparty_top_up: U512,
+    proposer_purse: URef,
+) -> u64 {
+    pausable::ensure_not_paused();
+
+    let caller = call_stack::caller().key();
+
+    ensure_neq!(caller, counterparty, MarketError::InvalidSwap);
+    ensure!(
+        proposer_top_up.is_zero() || counterparty_top_up.is_zero(),
+        MarketError::InvalidSwap
+    );
+
+    ensure_valid_items(&proposer_items);
+    ensure_valid_items(&counterparty_items);
+    for item in &proposer_items {
+        ensure_listable(item.nft_contract, &item.token_id, caller);
+    }
+
+    let swap_id = next_post_id();
+
+    let proposal = SwapProposal {
+        proposer: caller,
+        proposer_items,
+        proposer_value,
+        proposer_top_up,
+        counterparty,
+        counterparty_items,
+        counterparty_value,
+        counterparty_top_up,
+    };
+
+    set_target_purse_by_post_id(swap_id, proposer_purse);
+
+    proposal.write(swap_id);
+
+    swap_id
+}
+
+/// Accept a swap proposal. Only the counterparty can accept, and the whole swap either goes
+/// through or reverts.
+pub fn accept_swap(swap_id: u64, source_purse: URef) {
+    pausable::ensure_not_paused();
+
+    let caller = call_stack::caller().key();
+    let proposal = SwapProposal::by_id(swap_id);
+
+    ensure_eq!(
+        caller,
+        proposal.counterparty,
+        MarketError::InvalidMethodAccess
+    );
+    for item in &proposal.counterparty_items {
+        ensure_listable(item.nft_contract, &item.token_id, caller);
+    }
+
+    let proposer_purse = o_unwrap!(target_purse_by_post_id(swap_id), MarketError::UnknownPostId);
+
+    swap_side(
+        &proposal.proposer_items,
+        proposal.proposer_value,
+        proposer_purse,
+        proposal.proposer,
+        proposal.counterparty,
+    );
+    swap_side(
+        &proposal.counterparty_items,
+        proposal.counterparty_value,
+        source_purse,
+        proposal.counterparty,
+        proposal.proposer,
+    );
+
+    if !proposal.proposer_top_up.is_zero() {
+        r_unwrap!(
+            casper_contract::contract_api::system::transfer_from_purse_to_purse(
+                proposer_purse,
+                source_purse,
+                proposal.proposer_top_up,
+                None,
+            )
+        );
+    }
+
+    if !proposal.counterparty_top_up.is_zero() {
+        r_unwrap!(
+            casper_contract::contract_api::system::transfer_from_purse_to_purse(
+                source_purse,
+                proposer_purse,
+                proposal.counterparty_top_up,
+                None,
+            )
+        );
+    }
+
+    unset_target_purse_by_post_id(swap_id);
+    SwapProposal::remove(swap_id);
 }
 
+/// Pay the royalties and the platform fee for one side of a swap out of `purse` and move its items
+/// to `target`.
+fn swap_side(items: &[BundleItem], value: U512, purse: URef, owner: Key, target: Key) {
+    pay_platform_fee(purse, platform_fee(value).unwrap_or_revert());
+
+    for (item, allocation) in items.iter().zip(allocate(value, items)) {
+        let (nft_contract_id, nft_contract) =
+            NftContractMetadata::by_package_hash(item.nft_contract);
+
+        collect_royalty(
+            &nft_contract,
+            &item.token_id,
+            purse,
+            owner,
+            owner,
+            target,
+            allocation,
+        );
+
+        ext::cep78::transfer(item.nft_contract, &item.token_id, owner, target);
+        remove_single_listing(nft_contract_id, &item.token_id);
+    }
+}
+
+/// Withdraw a swap proposal, or decline it as the counterparty.
+pub fn cancel_swap(swap_id: u64) {
+    let caller = call_stack::caller().key();
+    let proposal = SwapProposal::by_id(swap_id);
+
+    ensure!(
+        caller == proposal.proposer || caller == proposal.counterparty,
+        MarketError::InvalidMethodAccess
+    );
+
+    unset_target_purse_by_post_id(swap_id);
+    SwapProposal::remove(swap_id);
+}
+
+/// Support trading the tokens of `nft_package`, whose transfers are filtered by
+/// `custodial_package`, if any.
+///
+/// Whether the custodial contract requires sales to be settled through it is asked once, here.
+/// Custodial contracts of the first release can't be asked, and are registered as
+/// `legacy_custodial`. Registering a collection again updates it in place, for its existing
+/// listings, too, which is how a change of the custodial contract is picked up.
 pub fn register_cep78_contract(
     nft_package: ContractPackageHash,
     custodial_package: Option<ContractPackageHash>,
+    legacy_custodial: Option<bool>,
 ) {
-    let mut counters = Counters::read();
-    let contract_id = counters.post_id;
-    counters.post_id += 1;
-    counters.write();
+    access::ensure_role(Role::Manager);
+
+    let legacy_custodial = legacy_custodial.unwrap_or(false);
+    let settlement_required = match custodial_package {
+        Some(custodial_package) if !legacy_custodial => {
+            ext::cep82::custodial::settlement_required(custodial_package)
+        }
+        _ => false,
+    };
+
+    let contract_id =
+        NftContractMetadata::try_id_by_package_hash(nft_package).unwrap_or_else(|| {
+            let mut counters = Counters::read();
+            let contract_id = counters.post_id;
+            counters.post_id += 1;
+            counters.write();
+            contract_id
+        });
 
     let entry = NftContractMetadata {
         nft_package,
         custodial_package,
+        legacy_custodial,
+        settlement_required,
     };
     entry.write(contract_id);
 }
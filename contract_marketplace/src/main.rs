@@ -12,8 +12,28 @@ forward_entrypoints! {
 forward_entrypoints! {
     ep: [
         bid,
+        bid_many,
         post,
+        quote,
         cancel,
+        active_listings,
+        listings_by_seller,
+        listings_by_collection,
+        post_bundle,
+        bid_bundle,
+        cancel_bundle,
+        propose_swap,
+        accept_swap,
+        cancel_swap,
+        withdraw_platform_fees,
+        set_platform_fee_bps,
+        migrate,
+        migrate_listings,
+        grant_role,
+        revoke_role,
+        has_role,
+        set_paused,
+        is_paused,
         register_cep78_contract,
     ]
 }
@@ -1,16 +1,31 @@
-use alloc::{format, string::String, vec::Vec};
+use alloc::{format, string::String, vec, vec::Vec};
 use base64::{prelude::BASE64_STANDARD_NO_PAD, Engine};
 use casper_contract::contract_api::runtime;
-use casper_types::{ContractPackageHash, Key, URef, U512};
-use contract_common::{b64_cl, o_unwrap, token::TokenIdentifier};
+use casper_contract::unwrap_or_revert::UnwrapOrRevert;
+use casper_types::{bytesrepr::ToBytes, ContractPackageHash, Key, URef, U512};
+use contract_common::{b64, b64_cl, o_unwrap, token::TokenIdentifier, FromNamedArg};
 
 use crate::{named_keys, serializable_structs, MarketError};
 
+/// Who bears the royalty of a sale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RoyaltyMode {
+    /// The royalty is taken out of the listing price.
+    SellerPays = 0,
+    /// The royalty is added on top of the listing price.
+    BuyerPays = 1,
+}
+
+impl FromNamedArg for RoyaltyMode {}
+
 serializable_structs! {
     #[derive(Debug, Clone, PartialEq, Eq)]
     pub struct NftContractMetadata {
         pub nft_package: ContractPackageHash,
         pub custodial_package: Option<ContractPackageHash>,
+        pub legacy_custodial: bool,
+        pub settlement_required: bool,
     }
 
     #[derive(Debug, Clone, PartialEq, Eq)]
@@ -20,6 +35,43 @@ serializable_structs! {
         pub owner: Key,
         pub token_id: TokenIdentifier,
         pub price: U512,
+        pub reserved_buyer: Option<Key>,
+        pub royalty_mode: RoyaltyMode,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct BundleItem {
+        pub nft_contract: ContractPackageHash,
+        pub token_id: TokenIdentifier,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct BundleEntry {
+        pub owner: Key,
+        pub items: Vec<BundleItem>,
+        pub price: U512,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct SwapProposal {
+        pub proposer: Key,
+        pub proposer_items: Vec<BundleItem>,
+        pub proposer_value: U512,
+        pub proposer_top_up: U512,
+
+        pub counterparty: Key,
+        pub counterparty_items: Vec<BundleItem>,
+        pub counterparty_value: U512,
+        pub counterparty_top_up: U512,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct SaleQuote {
+        pub price: U512,
+        pub royalty: U512,
+        pub platform_fee: U512,
+        pub seller_proceeds: U512,
+        pub total_due: U512,
     }
 
     #[derive(Debug, Clone, Default, PartialEq, Eq)]
@@ -30,16 +82,28 @@ serializable_structs! {
     }
 }
 
+/// Version of the layout of the contract state. Bump it whenever `migrate` has something new to do.
+pub const SCHEMA_VERSION: u32 = 1;
+
 named_keys! {
-    all_named_keys():
+    all_named_keys(platform_fee_bps: u64):
     // Common named keys
-    dict nft_contract_metadata_by_id: NftContractMetadata;
+    vdict nft_contract_metadata_by_id: NftContractMetadata;
     dict nft_contract_id_by_package_hash: u64;
     val counters: Counters = Counters::default();
+    val platform_fee_bps: u64 = platform_fee_bps;
+    val schema_version: u32 = SCHEMA_VERSION;
 
     // Order book specificic named keys
-    dict orderbook_entry_by_id: OrderbookEntry;
+    vdict orderbook_entry_by_id: OrderbookEntry;
     dict post_id_by_token_id: u64;
+    vdict bundle_entry_by_id: BundleEntry;
+    vdict swap_proposal_by_id: SwapProposal;
+
+    // Listing indexes, see `ListingIndex`
+    dict listing_index_entries: u64;
+    dict listing_index_positions: u64;
+    dict listing_index_lengths: u64;
 }
 
 fn package_hash_key(package: ContractPackageHash) -> String {
@@ -51,18 +115,22 @@ fn u64_key(id: u64) -> String {
 }
 
 impl NftContractMetadata {
+    pub fn try_by_id(id: u64) -> Option<Self> {
+        nft_contract_metadata_by_id::try_read(&u64_key(id))
+    }
+
     pub fn by_id(id: u64) -> Self {
-        o_unwrap!(
-            nft_contract_metadata_by_id::try_read(&u64_key(id)),
-            MarketError::UnsupportedNFTContract
-        )
+        o_unwrap!(Self::try_by_id(id), MarketError::UnsupportedNFTContract)
+    }
+
+    pub fn try_id_by_package_hash(package: ContractPackageHash) -> Option<u64> {
+        nft_contract_id_by_package_hash::try_read(&package_hash_key(package))
     }
 
     pub fn by_package_hash(package: ContractPackageHash) -> (u64, Self) {
         o_unwrap!(
-            nft_contract_id_by_package_hash::try_read(&package_hash_key(package)).and_then(|id| {
-                nft_contract_metadata_by_id::try_read(&u64_key(id)).map(|metadata| (id, metadata))
-            }),
+            Self::try_id_by_package_hash(package)
+                .and_then(|id| Self::try_by_id(id).map(|metadata| (id, metadata))),
             MarketError::UnsupportedNFTContract
         )
     }
@@ -84,33 +152,230 @@ impl Counters {
 }
 
 impl OrderbookEntry {
+    pub fn try_by_id(id: u64) -> Option<Self> {
+        orderbook_entry_by_id::try_read(&u64_key(id))
+    }
+
+    pub fn by_id(id: u64) -> Self {
+        o_unwrap!(Self::try_by_id(id), MarketError::UnknownPostId)
+    }
+
+    pub fn write(self, id: u64) {
+        orderbook_entry_by_id::write(&u64_key(id), self);
+    }
+
+    pub fn remove(id: u64) {
+        orderbook_entry_by_id::remove(&u64_key(id));
+    }
+
+    /// Rewrite the listings with the given ids in the current layout. Returns how many needed it.
+    pub fn upgrade(ids: core::ops::Range<u64>) -> u32 {
+        orderbook_entry_by_id::upgrade(ids.map(u64_key))
+    }
+
+    fn indexes(&self) -> [ListingIndex; 3] {
+        [
+            ListingIndex::Active,
+            ListingIndex::BySeller(self.owner),
+            ListingIndex::ByCollection(self.nft_contract_id),
+        ]
+    }
+
+    /// Add the listing to all the listing indexes.
+    pub fn index(&self, id: u64) {
+        for index in self.indexes() {
+            index.insert(id);
+        }
+    }
+
+    /// Remove the listing from all the listing indexes.
+    pub fn unindex(&self, id: u64) {
+        for index in self.indexes() {
+            index.remove(id);
+        }
+    }
+}
+
+/// A secondary index over active listings.
+///
+/// Each index is stored as a dense list of post ids, so that it can be paginated, together with
+/// the position of each post id in the list, so that removal doesn't need a scan. Removal moves
+/// the last element into the freed position, so the order of the list is not stable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListingIndex {
+    Active,
+    BySeller(Key),
+    ByCollection(u64),
+}
+
+impl ListingIndex {
+    fn prefix(&self) -> Vec<u8> {
+        match self {
+            ListingIndex::Active => vec![0],
+            ListingIndex::BySeller(seller) => {
+                let mut prefix = vec![1];
+                prefix.append(&mut seller.to_bytes().unwrap_or_revert());
+                prefix
+            }
+            ListingIndex::ByCollection(nft_contract_id) => {
+                let mut prefix = vec![2];
+                prefix.extend_from_slice(&nft_contract_id.to_be_bytes());
+                prefix
+            }
+        }
+    }
+
+    fn length_key(&self) -> String {
+        BASE64_STANDARD_NO_PAD.encode(self.prefix())
+    }
+
+    fn item_key(&self, n: u64) -> String {
+        let mut key = self.prefix();
+        key.extend_from_slice(&n.to_be_bytes());
+        BASE64_STANDARD_NO_PAD.encode(key)
+    }
+
+    pub fn len(&self) -> u64 {
+        listing_index_lengths::try_read(&self.length_key()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn set_len(&self, len: u64) {
+        listing_index_lengths::write(&self.length_key(), len);
+    }
+
+    pub fn contains(&self, post_id: u64) -> bool {
+        listing_index_positions::try_read(&self.item_key(post_id)).is_some()
+    }
+
+    /// Append `post_id` to the index, unless it's already in it.
+    pub fn insert(&self, post_id: u64) {
+        if self.contains(post_id) {
+            return;
+        }
+
+        let position = self.len();
+
+        listing_index_entries::write(&self.item_key(position), post_id);
+        listing_index_positions::write(&self.item_key(post_id), position);
+        self.set_len(position + 1);
+    }
+
+    pub fn remove(&self, post_id: u64) {
+        let position = match listing_index_positions::try_read(&self.item_key(post_id)) {
+            Some(position) => position,
+            None => return,
+        };
+        let last_position = self.len() - 1;
+
+        if position != last_position {
+            let last_post_id = listing_index_entries::read(&self.item_key(last_position));
+            listing_index_entries::write(&self.item_key(position), last_post_id);
+            listing_index_positions::write(&self.item_key(last_post_id), position);
+        }
+
+        listing_index_entries::remove(&self.item_key(last_position));
+        listing_index_positions::remove(&self.item_key(post_id));
+        self.set_len(last_position);
+    }
+
+    /// Return up to `limit` post ids, starting from `offset`.
+    pub fn page(&self, offset: u64, limit: u64) -> Vec<u64> {
+        let end = self.len().min(offset.saturating_add(limit));
+
+        (offset..end)
+            .map(|position| listing_index_entries::read(&self.item_key(position)))
+            .collect()
+    }
+}
+
+impl BundleEntry {
     pub fn by_id(id: u64) -> Self {
         o_unwrap!(
-            orderbook_entry_by_id::try_read(&u64_key(id)),
+            bundle_entry_by_id::try_read(&u64_key(id)),
             MarketError::UnknownPostId
         )
     }
 
     pub fn write(self, id: u64) {
-        orderbook_entry_by_id::write(&u64_key(id), self);
+        bundle_entry_by_id::write(&u64_key(id), self);
     }
 
     pub fn remove(id: u64) {
-        orderbook_entry_by_id::remove(&u64_key(id));
+        bundle_entry_by_id::remove(&u64_key(id));
+    }
+
+    pub fn allocations(&self) -> Vec<U512> {
+        allocate(self.price, &self.items)
     }
 }
 
-pub fn post_id_by_token_id(token_id: &TokenIdentifier) -> Option<u64> {
-    post_id_by_token_id::try_read(&b64_cl(token_id))
+impl SwapProposal {
+    pub fn by_id(id: u64) -> Self {
+        o_unwrap!(
+            swap_proposal_by_id::try_read(&u64_key(id)),
+            MarketError::UnknownPostId
+        )
+    }
+
+    pub fn write(self, id: u64) {
+        swap_proposal_by_id::write(&u64_key(id), self);
+    }
+
+    pub fn remove(id: u64) {
+        swap_proposal_by_id::remove(&u64_key(id));
+    }
 }
 
-pub fn set_post_id_by_token_id(token_id: &TokenIdentifier, id: Option<u64>) {
+/// Split `value` equally across `items`.
+///
+/// The rounding remainder goes to the last item, so the allocations always add up to `value`.
+pub fn allocate(value: U512, items: &[BundleItem]) -> Vec<U512> {
+    let share = o_unwrap!(
+        value.checked_div(items.len().into()),
+        MarketError::InvalidBundle
+    );
+
+    let mut allocations = vec![share; items.len()];
+    if let Some(last) = allocations.last_mut() {
+        *last += value - share * U512::from(items.len());
+    }
+
+    allocations
+}
+
+/// Key of the listing of `token_id` in `nft_contract_id`. Hashed, since dictionary keys are limited
+/// to 64 bytes and token hashes alone take that many.
+fn token_listing_key(nft_contract_id: u64, token_id: &TokenIdentifier) -> String {
+    let mut key = nft_contract_id.to_be_bytes().to_vec();
+    key.append(&mut token_id.to_bytes().unwrap_or_revert());
+    b64(runtime::blake2b(key))
+}
+
+pub fn post_id_by_token_id(nft_contract_id: u64, token_id: &TokenIdentifier) -> Option<u64> {
+    post_id_by_token_id::try_read(&token_listing_key(nft_contract_id, token_id))
+}
+
+pub fn set_post_id_by_token_id(nft_contract_id: u64, token_id: &TokenIdentifier, id: Option<u64>) {
+    let key = token_listing_key(nft_contract_id, token_id);
     match id {
-        Some(id) => post_id_by_token_id::write(&b64_cl(token_id), id),
-        None => post_id_by_token_id::remove(&b64_cl(token_id)),
+        Some(id) => post_id_by_token_id::write(&key, id),
+        None => post_id_by_token_id::remove(&key),
     }
 }
 
+/// The post id listings used to be found under, by token id alone, whichever their collection.
+pub fn legacy_post_id_by_token_id(token_id: &TokenIdentifier) -> Option<u64> {
+    post_id_by_token_id::try_read(&b64_cl(token_id))
+}
+
+pub fn unset_legacy_post_id_by_token_id(token_id: &TokenIdentifier) {
+    post_id_by_token_id::remove(&b64_cl(token_id));
+}
+
 pub fn set_target_purse_by_post_id(post_id: u64, purse: URef) {
     runtime::put_key(&format!("target_purse_{post_id}"), purse.into());
 }
@@ -3,7 +3,7 @@
 
 extern crate alloc;
 
-use alloc::{string::String, vec::Vec};
+use alloc::{string::String, vec, vec::Vec};
 use casper_contract::{
     contract_api::{
         account,
@@ -14,9 +14,15 @@ use casper_contract::{
 };
 use casper_types::{
     bytesrepr::{self, Bytes, FromBytes},
-    ContractHash, RuntimeArgs, U512,
+    contracts::NamedKeys,
+    runtime_args, CLValue, ContractHash, ContractPackageHash, EntryPoint, EntryPointAccess,
+    EntryPointType, EntryPoints, Key, RuntimeArgs, URef, U512,
 };
-use casper_types::{CLType, CLTyped};
+use casper_types::{CLType, CLTyped, URefAddr};
+use contract_common::{call_stack, ext::cep78, token::TokenIdentifier};
+
+/// Royalty the reentrant custodial contract asks for.
+const REENTRANT_ROYALTY: u64 = 1_000;
 
 // Simple wrapper for a contract call that memoizes the result in the caller's
 // context so that it can be retrieved later.
@@ -43,10 +49,252 @@ pub extern "C" fn call() {
             system::transfer_from_purse_to_purse(main_purse, purse, amount, None)
                 .unwrap_or_revert();
         }
+        "install_reentrant_custodial" => {
+            let marketplace = runtime::get_named_arg::<ContractHash>("marketplace");
+            install_reentrant_custodial(marketplace);
+        }
+        "install_custodial_caller" => install_custodial_caller(),
+        "install_cep78_client" => install_cep78_client(),
         _ => panic!("Unknown action!"),
     }
 }
 
+/// Install a contract that poses as the custodial contract of a collection, and calls back into
+/// `marketplace` with a bid when it's paid a royalty.
+fn install_reentrant_custodial(marketplace: ContractHash) {
+    let mut entry_points = EntryPoints::new();
+    for (name, ret) in [
+        ("calculate_royalty", U512::cl_type()),
+        ("settlement_required", bool::cl_type()),
+        ("pay_royalty", CLType::Unit),
+    ] {
+        entry_points.add_entry_point(EntryPoint::new(
+            name,
+            vec![],
+            ret,
+            EntryPointAccess::Public,
+            EntryPointType::Contract,
+        ));
+    }
+
+    let mut named_keys = NamedKeys::new();
+    named_keys.insert("marketplace".into(), Key::from(marketplace));
+
+    storage::new_contract(
+        entry_points,
+        Some(named_keys),
+        Some("reentrant_custodial_package".into()),
+        Some("reentrant_custodial_access".into()),
+    );
+}
+
+/// Install a contract that calls a custodial contract the way a misbehaving marketplace would.
+fn install_custodial_caller() {
+    let mut entry_points = EntryPoints::new();
+    for name in ["pay_royalty_twice", "pay_transfer_fee"] {
+        entry_points.add_entry_point(EntryPoint::new(
+            name,
+            vec![],
+            CLType::Unit,
+            EntryPointAccess::Public,
+            EntryPointType::Contract,
+        ));
+    }
+
+    let (contract_hash, _) = storage::new_contract(
+        entry_points,
+        None,
+        Some("custodial_caller_package".into()),
+        Some("custodial_caller_access".into()),
+    );
+    runtime::put_key("custodial_caller_contract", contract_hash.into());
+}
+
+#[no_mangle]
+pub extern "C" fn pay_royalty_twice() {
+    let custodial = runtime::get_named_arg::<ContractPackageHash>("custodial");
+    let args = runtime_args! {
+        "token_contract" => runtime::get_named_arg::<ContractPackageHash>("token_contract"),
+        "token_id" => runtime::get_named_arg::<u64>("token_id"),
+        "source_purse" => runtime::get_named_arg::<URef>("source_purse"),
+        "payer" => runtime::get_named_arg::<Key>("payer"),
+        "source_key" => runtime::get_named_arg::<Key>("source_key"),
+        "target_key" => runtime::get_named_arg::<Key>("target_key"),
+        "payment_amount" => runtime::get_named_arg::<U512>("payment_amount"),
+    };
+
+    // pay for the same sale again without transferring the token in between
+    for _ in 0..2 {
+        runtime::call_versioned_contract::<()>(custodial, None, "pay_royalty", args.clone());
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pay_transfer_fee() {
+    let custodial = runtime::get_named_arg::<ContractPackageHash>("custodial");
+
+    runtime::call_versioned_contract::<()>(
+        custodial,
+        None,
+        "pay_transfer_fee",
+        runtime_args! {
+            "token_contract" => runtime::get_named_arg::<ContractPackageHash>("token_contract"),
+            "token_id" => runtime::get_named_arg::<u64>("token_id"),
+            "source_purse" => runtime::get_named_arg::<URef>("source_purse"),
+            "target_key" => runtime::get_named_arg::<Key>("target_key"),
+        },
+    );
+}
+
+/// Install a contract that drives a CEP-78 collection through `contract_common::ext::cep78`, on
+/// its own behalf.
+fn install_cep78_client() {
+    let mut entry_points = EntryPoints::new();
+    for (name, ret) in [
+        ("cep78_mint", String::cl_type()),
+        ("cep78_metadata", String::cl_type()),
+        ("cep78_token_issuer", Option::<Key>::cl_type()),
+        ("cep78_balance_of", u64::cl_type()),
+        ("cep78_approve", CLType::Unit),
+        ("cep78_revoke", CLType::Unit),
+        ("cep78_set_approval_for_all", CLType::Unit),
+        ("cep78_is_approved_for_all", bool::cl_type()),
+        ("cep78_is_approved", bool::cl_type()),
+        ("cep78_burn", CLType::Unit),
+    ] {
+        entry_points.add_entry_point(EntryPoint::new(
+            name,
+            vec![],
+            ret,
+            EntryPointAccess::Public,
+            EntryPointType::Contract,
+        ));
+    }
+
+    let (contract_hash, _) = storage::new_contract(
+        entry_points,
+        None,
+        Some("cep78_client_package".into()),
+        Some("cep78_client_access".into()),
+    );
+    runtime::put_key("cep78_client_contract", contract_hash.into());
+}
+
+fn collection() -> ContractPackageHash {
+    runtime::get_named_arg("collection")
+}
+
+fn token_id() -> TokenIdentifier {
+    TokenIdentifier::try_load_from_runtime_args().unwrap_or_revert()
+}
+
+fn ret<T: CLTyped + bytesrepr::ToBytes>(value: T) {
+    runtime::ret(CLValue::from_t(value).unwrap_or_revert());
+}
+
+/// Mint a token to the client itself, and return its identifier.
+#[no_mangle]
+pub extern "C" fn cep78_mint() {
+    let receipt = cep78::mint(
+        collection(),
+        call_stack::current_contract().into(),
+        runtime::get_named_arg("token_meta_data"),
+    );
+    ret(receipt.token_id);
+}
+
+#[no_mangle]
+pub extern "C" fn cep78_metadata() {
+    ret(cep78::metadata(collection(), &token_id()));
+}
+
+#[no_mangle]
+pub extern "C" fn cep78_token_issuer() {
+    let token_issuers = runtime::get_named_arg::<URefAddr>("token_issuers");
+    ret(cep78::token_issuer(token_issuers, &token_id()));
+}
+
+#[no_mangle]
+pub extern "C" fn cep78_balance_of() {
+    ret(cep78::balance_of(
+        collection(),
+        runtime::get_named_arg("token_owner"),
+    ));
+}
+
+#[no_mangle]
+pub extern "C" fn cep78_approve() {
+    cep78::approve(collection(), &token_id(), runtime::get_named_arg("spender"));
+}
+
+#[no_mangle]
+pub extern "C" fn cep78_revoke() {
+    cep78::revoke(collection(), &token_id());
+}
+
+#[no_mangle]
+pub extern "C" fn cep78_set_approval_for_all() {
+    cep78::set_approval_for_all(
+        collection(),
+        runtime::get_named_arg("approve_all"),
+        runtime::get_named_arg("operator"),
+    );
+}
+
+#[no_mangle]
+pub extern "C" fn cep78_is_approved_for_all() {
+    ret(cep78::is_approved_for_all(
+        collection(),
+        runtime::get_named_arg("token_owner"),
+        runtime::get_named_arg("operator"),
+    ));
+}
+
+#[no_mangle]
+pub extern "C" fn cep78_is_approved() {
+    ret(cep78::is_approved(
+        collection(),
+        &token_id(),
+        runtime::get_named_arg("owner"),
+        runtime::get_named_arg("spender"),
+    ));
+}
+
+#[no_mangle]
+pub extern "C" fn cep78_burn() {
+    cep78::burn(collection(), &token_id());
+}
+
+#[no_mangle]
+pub extern "C" fn calculate_royalty() {
+    runtime::ret(CLValue::from_t(U512::from(REENTRANT_ROYALTY)).unwrap_or_revert());
+}
+
+#[no_mangle]
+pub extern "C" fn settlement_required() {
+    runtime::ret(CLValue::from_t(false).unwrap_or_revert());
+}
+
+#[no_mangle]
+pub extern "C" fn pay_royalty() {
+    let marketplace = runtime::get_key("marketplace")
+        .and_then(Key::into_hash)
+        .map(ContractHash::new)
+        .unwrap_or_revert();
+    let source_purse = runtime::get_named_arg::<URef>("source_purse");
+
+    // the post id doesn't matter, the marketplace has to refuse before looking at it
+    runtime::call_contract::<()>(
+        marketplace,
+        "bid",
+        runtime_args! {
+            "post_id" => 0u64,
+            "source_purse" => source_purse,
+            "amount" => U512::zero(),
+        },
+    );
+}
+
 struct AllBytes {
     data: Vec<u8>,
 }
@@ -16,3 +16,22 @@
     cp target/wasm32-unknown-unknown/release/testutil.wasm ../wasm/testutil.wasm
 )
 
+(
+    cd session_transfer
+    cargo build --release --target wasm32-unknown-unknown --features onchain,test-support
+)
+
+# the contracts as first released, which the upgrade tests install before upgrading them
+v0_commit=531ecd0c31c2828d96e3ef32c799078bc7f0de2f
+(
+    baseline=target/v0
+    rm -rf "$baseline"
+    mkdir -p "$baseline"
+    git archive "$v0_commit" contract_common contract_marketplace contract_custodial \
+        | tar -x -C "$baseline"
+
+    cd "$baseline/contract_marketplace"
+    cargo build --release --target wasm32-unknown-unknown --features onchain,test-support
+    cd ../contract_custodial
+    cargo build --release --target wasm32-unknown-unknown --features onchain,test-support
+)
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+
+//! Session code that moves a token of a custodial-filtered CEP-78 collection from the calling
+//! account to `target_key`, without a marketplace.
+//!
+//! The custodial contract's transfer fee is moved out of the account's main purse into a purse of
+//! its own first, so the custodial contract never gets access to the main purse.
+
+extern crate alloc;
+
+use casper_contract::{
+    contract_api::{account, runtime, system},
+    unwrap_or_revert::UnwrapOrRevert,
+};
+use casper_types::{ApiError, ContractPackageHash, Key};
+use contract_common::{ext, token::TokenIdentifier};
+
+#[no_mangle]
+pub extern "C" fn call() {
+    let custodial_package = runtime::get_named_arg::<ContractPackageHash>("custodial_package");
+    let token_contract = runtime::get_named_arg::<ContractPackageHash>("token_contract");
+    let target_key = runtime::get_named_arg::<Key>("target_key");
+    let token_id = TokenIdentifier::try_load_from_runtime_args()
+        .unwrap_or_revert_with(ApiError::MissingArgument);
+
+    // without a fee, direct transfers are disabled and `pay_transfer_fee` reverts saying so
+    let fee = ext::cep82::custodial::transfer_fee(custodial_package).unwrap_or_default();
+    let fee_purse = system::create_purse();
+    if !fee.is_zero() {
+        system::transfer_from_purse_to_purse(account::get_main_purse(), fee_purse, fee, None)
+            .unwrap_or_revert();
+    }
+
+    ext::cep82::custodial::pay_transfer_fee(
+        custodial_package,
+        token_contract,
+        &token_id,
+        fee_purse,
+        target_key,
+    );
+
+    let source_key = Key::Account(runtime::get_caller());
+    ext::cep78::transfer(token_contract, &token_id, source_key, target_key);
+}
@@ -2,13 +2,21 @@
 
 pub mod util;
 
-use casper_types::{U256, U512};
+mod reverts;
+
+use casper_types::{ContractHash, ContractPackageHash, Key, U256, U512};
 use util::{
-    deploy::{deploy_cep78, deploy_cep82_custodial, deploy_cep82_marketplace, deploy_erc20},
+    deploy::{
+        deploy_cep78, deploy_cep78_with_public_minting, deploy_cep82_custodial,
+        deploy_cep82_custodial_v0, deploy_cep82_marketplace, deploy_cep82_marketplace_v0,
+        deploy_erc20, upgrade_cep82_custodial, upgrade_cep82_marketplace,
+    },
     *,
 };
 
-use crate::util::state::RoyaltyStep;
+use crate::util::state::{
+    BundleItem, MarketplaceConfig, MarketplaceMode, RecipientList, Role, RoyaltyMode, RoyaltyStep,
+};
 
 // Test for basic functionality
 #[test]
@@ -74,6 +82,10 @@ fn post_and_trade() {
 
     cep78::register_owner(&mut context, cep78_hash, buyer.address.into());
     cep78::register_owner(&mut context, cep78_hash, manager.address.into());
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_collection(&mut context, cep82_cs_hash, cep78_package);
+    set_current_sender(None);
     cep82::marketplace::register_nft(
         &mut context,
         cep82_mp_hash,
@@ -94,6 +106,8 @@ fn post_and_trade() {
         id1,
         1_000_000.into(),
         post_purse,
+        None,
+        None,
     );
 
     // let root_balance_before = erc20::balance_of(&mut context, erc20_hash, root.into());
@@ -117,3 +131,2738 @@ fn post_and_trade() {
     let owner = cep78::owner_of(&mut context, cep78_hash, id1);
     assert_eq!(owner, buyer.address.into());
 }
+
+struct Market {
+    custodial_hash: ContractHash,
+    custodial_package: ContractPackageHash,
+    cep78_hash: ContractHash,
+    cep78_package: ContractPackageHash,
+    marketplace_hash: ContractHash,
+    marketplace_package: ContractPackageHash,
+}
+
+/// Deploy a custodial-filtered CEP-78 collection and a marketplace that supports it.
+fn setup_market(context: &mut TestContext, manager: Key, owners: &[Key]) -> Market {
+    setup_market_with(context, manager, owners, vec![], RoyaltyStep::basic())
+}
+
+/// Like [`setup_market`], with a custom configuration of the custodial contract.
+fn setup_market_with(
+    context: &mut TestContext,
+    manager: Key,
+    owners: &[Key],
+    whitelisted_marketplaces: Vec<ContractPackageHash>,
+    royalty_structure: Vec<RoyaltyStep>,
+) -> Market {
+    let (custodial_hash, custodial_package) = deploy_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        whitelisted_marketplaces,
+        royalty_structure,
+        manager,
+    );
+
+    let (cep78_hash, cep78_package) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(custodial_hash.into()),
+    );
+
+    let (marketplace_hash, marketplace_package) =
+        deploy_cep82_marketplace(&mut context.builder, context.account.address);
+
+    for owner in owners {
+        cep78::register_owner(context, cep78_hash, *owner);
+    }
+
+    // the custodial contract only takes royalties for the collection it's pointed at
+    set_current_sender(manager.into_account());
+    cep82::custodial::set_collection(context, custodial_hash, cep78_package);
+
+    // registering collections takes the manager role, which the installing account holds
+    set_current_sender(None);
+    cep82::marketplace::register_nft(
+        context,
+        marketplace_hash,
+        cep78_package,
+        Some(custodial_package),
+    );
+
+    Market {
+        custodial_hash,
+        custodial_package,
+        cep78_hash,
+        cep78_package,
+        marketplace_hash,
+        marketplace_package,
+    }
+}
+
+/// Make the custodial contract of `market` require settlement, and register the collection again,
+/// which is when the marketplace learns about it.
+fn require_settlement(context: &mut TestContext, market: &Market, manager: &UserAccount) {
+    set_current_sender(manager.address);
+    cep82::custodial::set_settlement_required(context, market.custodial_hash, true);
+    assert!(cep82::custodial::settlement_required(
+        context,
+        market.custodial_hash
+    ));
+
+    set_current_sender(None);
+    cep82::marketplace::register_nft(
+        context,
+        market.marketplace_hash,
+        market.cep78_package,
+        Some(market.custodial_package),
+    );
+}
+
+/// Mint a token to `seller` and list it on the marketplace.
+fn mint_and_post(
+    context: &mut TestContext,
+    market: &Market,
+    seller: &UserAccount,
+    price: u64,
+    reserved_buyer: Option<Key>,
+    royalty_mode: Option<RoyaltyMode>,
+) -> (u64, u64) {
+    set_current_sender(None);
+    let (_, _, token_id) = cep78::mint(context, market.cep78_hash, seller.key());
+    let post_purse = new_purse(context, seller.address, "post_purse", U512::from(0));
+
+    set_current_sender(seller.address);
+    cep78::approve(
+        context,
+        market.cep78_hash,
+        token_id,
+        market.marketplace_hash.into(),
+    );
+    let post_id = cep82::marketplace::post(
+        context,
+        market.marketplace_hash,
+        market.cep78_package,
+        token_id,
+        price.into(),
+        post_purse,
+        reserved_buyer,
+        royalty_mode,
+    );
+
+    (token_id, post_id)
+}
+
+#[test]
+fn reserved_listing_trade() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (token_id, post_id) = mint_and_post(
+        &mut context,
+        &market,
+        &seller,
+        1_000_000,
+        Some(buyer.key()),
+        None,
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+
+    let owner = cep78::owner_of(&mut context, market.cep78_hash, token_id);
+    assert_eq!(owner, buyer.key());
+}
+
+#[test]
+fn bid_many_skips_unfillable_listings() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (token1, post1) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+    let (token2, post2) = mint_and_post(&mut context, &market, &seller, 2_000_000, None, None);
+    let (token3, post3) = mint_and_post(
+        &mut context,
+        &market,
+        &seller,
+        1_000_000,
+        Some(seller.key()),
+        None,
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    let (count, total) = cep82::marketplace::bid_many(
+        &mut context,
+        market.marketplace_hash,
+        vec![post1, post2, post3],
+        bid_purse,
+        U512::from(5_000_000),
+        false,
+    );
+
+    assert_eq!(count, 2);
+    assert_eq!(total, U512::from(3_000_000));
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, token1),
+        buyer.key()
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, token2),
+        buyer.key()
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, token3),
+        seller.key()
+    );
+}
+
+#[test]
+fn bid_many_skips_listings_the_purse_cannot_cover() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (token1, post1) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+    let (token2, post2) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    // enough for one listing, within a `max_total` that would allow both
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(1_500_000),
+    );
+    let (count, total) = cep82::marketplace::bid_many(
+        &mut context,
+        market.marketplace_hash,
+        vec![post1, post2],
+        bid_purse,
+        U512::from(5_000_000),
+        false,
+    );
+
+    assert_eq!(count, 1);
+    assert_eq!(total, U512::from(1_000_000));
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, token1),
+        buyer.key()
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, token2),
+        seller.key()
+    );
+    assert_eq!(
+        context.builder.get_purse_balance(bid_purse),
+        U512::from(500_000)
+    );
+}
+
+#[test]
+fn bid_many_skips_listings_the_custodial_would_refuse() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market(&mut context, manager.key(), &[seller.key(), buyer.key()]);
+
+    let (token_id, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    let bid_all = |context: &mut TestContext| {
+        set_current_sender(buyer.address);
+        cep82::marketplace::bid_many(
+            context,
+            market.marketplace_hash,
+            vec![post_id],
+            bid_purse,
+            U512::from(5_000_000),
+            false,
+        )
+    };
+
+    set_current_sender(manager.address);
+    cep82::marketplace::set_paused(&mut context, market.custodial_hash, true);
+    assert_eq!(bid_all(&mut context), (0, U512::zero()));
+
+    set_current_sender(manager.address);
+    cep82::marketplace::set_paused(&mut context, market.custodial_hash, false);
+    cep82::custodial::set_transfer_lock(&mut context, market.custodial_hash, 1_000);
+    assert_eq!(bid_all(&mut context), (0, U512::zero()));
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, token_id),
+        seller.key()
+    );
+
+    context.block_time = 1_000;
+    assert_eq!(bid_all(&mut context), (1, U512::from(1_000_000)));
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, token_id),
+        buyer.key()
+    );
+}
+
+#[test]
+fn bundle_trade() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market_with(
+        &mut context,
+        manager.key(),
+        &[seller.key(), buyer.key()],
+        vec![],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(1_000),
+        }],
+    );
+
+    // a second collection, with a royalty of its own, sold in the same bundle
+    let (other_custodial_hash, other_custodial_package) = deploy_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        vec![],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(500),
+        }],
+        manager.key(),
+    );
+    let (other_cep78_hash, other_cep78_package) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(other_custodial_hash.into()),
+    );
+    cep78::register_owner(&mut context, other_cep78_hash, seller.key());
+    cep78::register_owner(&mut context, other_cep78_hash, buyer.key());
+    set_current_sender(manager.address);
+    cep82::custodial::set_collection(&mut context, other_custodial_hash, other_cep78_package);
+    set_current_sender(None);
+    cep82::marketplace::register_nft(
+        &mut context,
+        market.marketplace_hash,
+        other_cep78_package,
+        Some(other_custodial_package),
+    );
+
+    let collections = [
+        (
+            market.cep78_hash,
+            market.cep78_package,
+            market.custodial_hash,
+        ),
+        (other_cep78_hash, other_cep78_package, other_custodial_hash),
+    ];
+
+    let mut items = vec![];
+    for (cep78_hash, cep78_package, _) in &collections {
+        set_current_sender(None);
+        let (_, _, token_id) = cep78::mint(&mut context, *cep78_hash, seller.key());
+
+        set_current_sender(seller.address);
+        cep78::approve(
+            &mut context,
+            *cep78_hash,
+            token_id,
+            market.marketplace_hash.into(),
+        );
+
+        items.push(BundleItem {
+            nft_contract: *cep78_package,
+            token_id,
+        });
+    }
+
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::from(0));
+    let bundle_id = cep82::bundle::post_bundle(
+        &mut context,
+        market.marketplace_hash,
+        items.clone(),
+        post_purse,
+        U512::from(4_000_000),
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::bundle::bid_bundle(
+        &mut context,
+        market.marketplace_hash,
+        bundle_id,
+        bid_purse,
+        U512::from(4_000_000),
+    );
+
+    // the price is split equally, and each custodial contract takes its royalty on its item's
+    // share, which it records as the sale price of the token
+    let expected = [(2_000_000u64, 200_000u64), (2_000_000, 100_000)];
+    set_current_sender(manager.address);
+    for ((item, (cep78_hash, _, custodial_hash)), (allocation, royalty)) in
+        items.iter().zip(collections).zip(expected)
+    {
+        let owner = cep78::owner_of(&mut context, cep78_hash, item.token_id);
+        assert_eq!(owner, buyer.key());
+
+        cep82::custodial::set_floor_follows_last_sale(&mut context, custodial_hash, true);
+        assert_eq!(
+            cep82::custodial::floor_value(&mut context, custodial_hash, item.token_id),
+            U512::from(allocation)
+        );
+        assert_eq!(
+            cep82::custodial::royalty_purse_balance(&mut context, custodial_hash),
+            U512::from(royalty)
+        );
+    }
+
+    assert_eq!(
+        context.builder.get_purse_balance(post_purse),
+        U512::from(3_700_000)
+    );
+}
+
+#[test]
+fn swap_with_top_up() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let alice = UserAccount::unique_account(&mut context, 20);
+    let bob = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market_with(
+        &mut context,
+        manager.key(),
+        &[alice.key(), bob.key()],
+        vec![],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(1_000),
+        }],
+    );
+
+    set_current_sender(None);
+    let (_, _, alice_token) = cep78::mint(&mut context, market.cep78_hash, alice.key());
+    let (_, _, bob_token) = cep78::mint(&mut context, market.cep78_hash, bob.key());
+
+    set_current_sender(alice.address);
+    cep78::approve(
+        &mut context,
+        market.cep78_hash,
+        alice_token,
+        market.marketplace_hash.into(),
+    );
+    let alice_purse = new_purse(
+        &mut context,
+        alice.address,
+        "swap_purse",
+        U512::from(10_000_000),
+    );
+    let swap_id = cep82::swap::propose_swap(
+        &mut context,
+        market.marketplace_hash,
+        bob.key(),
+        vec![BundleItem {
+            nft_contract: market.cep78_package,
+            token_id: alice_token,
+        }],
+        U512::from(2_000_000),
+        vec![BundleItem {
+            nft_contract: market.cep78_package,
+            token_id: bob_token,
+        }],
+        U512::from(1_000_000),
+        U512::from(1_000_000),
+        U512::zero(),
+        alice_purse,
+    );
+
+    set_current_sender(bob.address);
+    cep78::approve(
+        &mut context,
+        market.cep78_hash,
+        bob_token,
+        market.marketplace_hash.into(),
+    );
+    let bob_purse = new_purse(
+        &mut context,
+        bob.address,
+        "swap_purse",
+        U512::from(10_000_000),
+    );
+    cep82::swap::accept_swap(&mut context, market.marketplace_hash, swap_id, bob_purse);
+
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, alice_token),
+        bob.key()
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, bob_token),
+        alice.key()
+    );
+
+    // each side pays the royalty on the value it declared for its own items, and Alice's top-up
+    // goes to Bob
+    assert_eq!(
+        context.builder.get_purse_balance(alice_purse),
+        U512::from(10_000_000 - 200_000 - 1_000_000)
+    );
+    assert_eq!(
+        context.builder.get_purse_balance(bob_purse),
+        U512::from(10_000_000 - 100_000 + 1_000_000)
+    );
+    assert_eq!(
+        cep82::custodial::royalty_purse_balance(&mut context, market.custodial_hash),
+        U512::from(300_000)
+    );
+
+    // the declared values are what the custodial contract records as the sale prices
+    set_current_sender(manager.address);
+    cep82::custodial::set_floor_follows_last_sale(&mut context, market.custodial_hash, true);
+    assert_eq!(
+        cep82::custodial::floor_value(&mut context, market.custodial_hash, alice_token),
+        U512::from(2_000_000)
+    );
+    assert_eq!(
+        cep82::custodial::floor_value(&mut context, market.custodial_hash, bob_token),
+        U512::from(1_000_000)
+    );
+}
+
+#[test]
+fn buyer_pays_royalty_on_top() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (token_id, post_id) = mint_and_post(
+        &mut context,
+        &market,
+        &seller,
+        1_000_000,
+        None,
+        Some(RoyaltyMode::BuyerPays),
+    );
+
+    let quote = cep82::marketplace::quote(&mut context, market.marketplace_hash, post_id);
+    // `RoyaltyStep::basic()` is a flat fee of 100
+    assert_eq!(quote.royalty, U512::from(100));
+    assert_eq!(quote.platform_fee, U512::zero());
+    assert_eq!(quote.seller_proceeds, U512::from(1_000_000));
+    assert_eq!(quote.total_due, U512::from(1_000_100));
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post_id,
+        bid_purse,
+        1_000_100.into(),
+    );
+
+    let owner = cep78::owner_of(&mut context, market.cep78_hash, token_id);
+    assert_eq!(owner, buyer.key());
+}
+
+#[test]
+fn platform_fees_can_be_changed_and_withdrawn() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let treasurer = UserAccount::unique_account(&mut context, 30);
+    let vault = UserAccount::unique_account(&mut context, 40);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (_, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    // the installing account holds every role, and the new fee applies to existing listings
+    set_current_sender(None);
+    cep82::marketplace::set_platform_fee_bps(&mut context, market.marketplace_hash, 250);
+    let quote = cep82::marketplace::quote(&mut context, market.marketplace_hash, post_id);
+    assert_eq!(quote.platform_fee, U512::from(25_000));
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+    assert_eq!(
+        cep82::marketplace::platform_fee_purse_balance(&mut context, market.marketplace_hash),
+        U512::from(25_000)
+    );
+
+    set_current_sender(None);
+    cep82::marketplace::grant_role(
+        &mut context,
+        market.marketplace_hash,
+        Role::Treasurer,
+        treasurer.key(),
+    );
+
+    let vault_purse = context
+        .builder
+        .get_account(vault.address)
+        .unwrap()
+        .main_purse();
+    let vault_balance = context.builder.get_purse_balance(vault_purse);
+
+    set_current_sender(treasurer.address);
+    cep82::marketplace::withdraw_platform_fees(
+        &mut context,
+        market.marketplace_hash,
+        vault.address,
+        U512::from(20_000),
+    );
+    assert_eq!(
+        cep82::marketplace::platform_fee_purse_balance(&mut context, market.marketplace_hash),
+        U512::from(5_000)
+    );
+    assert_eq!(
+        context.builder.get_purse_balance(vault_purse),
+        vault_balance + U512::from(20_000)
+    );
+}
+
+#[test]
+fn platform_fee_is_taken_on_bundles_and_swaps() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let alice = UserAccount::unique_account(&mut context, 20);
+    let bob = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market_with(
+        &mut context,
+        manager.key(),
+        &[alice.key(), bob.key()],
+        vec![],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(1_000),
+        }],
+    );
+
+    set_current_sender(None);
+    cep82::marketplace::set_platform_fee_bps(&mut context, market.marketplace_hash, 250);
+    let (_, _, bundled_token) = cep78::mint(&mut context, market.cep78_hash, alice.key());
+    let (_, _, alice_token) = cep78::mint(&mut context, market.cep78_hash, alice.key());
+    let (_, _, bob_token) = cep78::mint(&mut context, market.cep78_hash, bob.key());
+
+    set_current_sender(alice.address);
+    for token_id in [bundled_token, alice_token] {
+        cep78::approve(
+            &mut context,
+            market.cep78_hash,
+            token_id,
+            market.marketplace_hash.into(),
+        );
+    }
+    let post_purse = new_purse(&mut context, alice.address, "post_purse", U512::zero());
+    let bundle_id = cep82::bundle::post_bundle(
+        &mut context,
+        market.marketplace_hash,
+        vec![BundleItem {
+            nft_contract: market.cep78_package,
+            token_id: bundled_token,
+        }],
+        post_purse,
+        U512::from(1_000_000),
+    );
+    let alice_purse = new_purse(
+        &mut context,
+        alice.address,
+        "swap_purse",
+        U512::from(10_000_000),
+    );
+    let swap_id = cep82::swap::propose_swap(
+        &mut context,
+        market.marketplace_hash,
+        bob.key(),
+        vec![BundleItem {
+            nft_contract: market.cep78_package,
+            token_id: alice_token,
+        }],
+        U512::from(2_000_000),
+        vec![BundleItem {
+            nft_contract: market.cep78_package,
+            token_id: bob_token,
+        }],
+        U512::from(1_000_000),
+        U512::zero(),
+        U512::zero(),
+        alice_purse,
+    );
+
+    set_current_sender(bob.address);
+    let bid_purse = new_purse(
+        &mut context,
+        bob.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::bundle::bid_bundle(
+        &mut context,
+        market.marketplace_hash,
+        bundle_id,
+        bid_purse,
+        U512::from(1_000_000),
+    );
+
+    // the seller of a bundle pays the fee, like the seller of a single listing
+    assert_eq!(
+        cep82::marketplace::platform_fee_purse_balance(&mut context, market.marketplace_hash),
+        U512::from(25_000)
+    );
+    assert_eq!(
+        context.builder.get_purse_balance(post_purse),
+        U512::from(1_000_000 - 100_000 - 25_000)
+    );
+
+    cep78::approve(
+        &mut context,
+        market.cep78_hash,
+        bob_token,
+        market.marketplace_hash.into(),
+    );
+    let bob_purse = new_purse(
+        &mut context,
+        bob.address,
+        "swap_purse",
+        U512::from(10_000_000),
+    );
+    cep82::swap::accept_swap(&mut context, market.marketplace_hash, swap_id, bob_purse);
+
+    // each side of a swap pays the fee on the value of the items it gives away
+    assert_eq!(
+        context.builder.get_purse_balance(alice_purse),
+        U512::from(10_000_000 - 200_000 - 50_000)
+    );
+    assert_eq!(
+        context.builder.get_purse_balance(bob_purse),
+        U512::from(10_000_000 - 100_000 - 25_000)
+    );
+    assert_eq!(
+        cep82::marketplace::platform_fee_purse_balance(&mut context, market.marketplace_hash),
+        U512::from(25_000 + 50_000 + 25_000)
+    );
+}
+
+#[test]
+fn listings_by_seller_drops_sold_listings() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (_, post1) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+    let (_, post2) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    let mut listings = cep82::marketplace::listings_by_seller(
+        &mut context,
+        market.marketplace_hash,
+        seller.key(),
+        0,
+        10,
+    );
+    listings.sort();
+    assert_eq!(listings, vec![post1, post2]);
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post1,
+        bid_purse,
+        1_000_000.into(),
+    );
+
+    let listings = cep82::marketplace::listings_by_seller(
+        &mut context,
+        market.marketplace_hash,
+        seller.key(),
+        0,
+        10,
+    );
+    assert_eq!(listings, vec![post2]);
+}
+
+#[test]
+fn bundle_sale_removes_single_listings_of_its_items() {
+    use contract_marketplace::MarketError;
+
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (token_id, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::zero());
+    let bundle_id = cep82::bundle::post_bundle(
+        &mut context,
+        market.marketplace_hash,
+        vec![BundleItem {
+            nft_contract: market.cep78_package,
+            token_id,
+        }],
+        post_purse,
+        1_000_000.into(),
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::bundle::bid_bundle(
+        &mut context,
+        market.marketplace_hash,
+        bundle_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+
+    // the single listing of the token was removed with the sale
+    let listings = cep82::marketplace::listings_by_seller(
+        &mut context,
+        market.marketplace_hash,
+        seller.key(),
+        0,
+        10,
+    );
+    assert!(listings.is_empty());
+    expect_revert(&mut context, MarketError::UnknownPostId, |context| {
+        cep82::marketplace::quote(context, market.marketplace_hash, post_id);
+    });
+}
+
+#[test]
+fn bundle_sale_only_removes_the_listing_of_its_own_collection() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (other_cep78_hash, other_cep78_package) =
+        deploy_cep78(&mut context.builder, context.account.address, None);
+    for owner in [seller.key(), buyer.key()] {
+        cep78::register_owner(&mut context, other_cep78_hash, owner);
+    }
+    set_current_sender(None);
+    cep82::marketplace::register_nft(
+        &mut context,
+        market.marketplace_hash,
+        other_cep78_package,
+        None,
+    );
+
+    // both collections number their tokens from the start, so the two tokens share an id
+    let (token_id, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+    let (other_token_id, other_post_id) = mint_and_post_plain(
+        &mut context,
+        market.marketplace_hash,
+        other_cep78_hash,
+        other_cep78_package,
+        &seller,
+        1_000_000,
+    );
+    assert_eq!(token_id, other_token_id);
+
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::zero());
+    let bundle_id = cep82::bundle::post_bundle(
+        &mut context,
+        market.marketplace_hash,
+        vec![BundleItem {
+            nft_contract: market.cep78_package,
+            token_id,
+        }],
+        post_purse,
+        1_000_000.into(),
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::bundle::bid_bundle(
+        &mut context,
+        market.marketplace_hash,
+        bundle_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+
+    // the listing of the sold token is gone, the other collection's is still up
+    let listings = cep82::marketplace::listings_by_seller(
+        &mut context,
+        market.marketplace_hash,
+        seller.key(),
+        0,
+        10,
+    );
+    assert_eq!(listings, vec![other_post_id]);
+    assert_ne!(post_id, other_post_id);
+
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        other_post_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, other_cep78_hash, other_token_id),
+        buyer.key()
+    );
+}
+
+#[test]
+fn pause_and_resume_trading() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (token_id, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    // the installing account is the pause admin by default
+    set_current_sender(None);
+    cep82::marketplace::set_paused(&mut context, market.marketplace_hash, true);
+    assert!(cep82::marketplace::is_paused(
+        &mut context,
+        market.marketplace_hash
+    ));
+
+    cep82::marketplace::set_paused(&mut context, market.marketplace_hash, false);
+    assert!(!cep82::marketplace::is_paused(
+        &mut context,
+        market.marketplace_hash
+    ));
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+
+    let owner = cep78::owner_of(&mut context, market.cep78_hash, token_id);
+    assert_eq!(owner, buyer.key());
+}
+
+/// Post a token of `cep78_hash` on the marketplace, in a way that every release supports.
+fn mint_and_post_plain(
+    context: &mut TestContext,
+    marketplace_hash: ContractHash,
+    cep78_hash: ContractHash,
+    cep78_package: ContractPackageHash,
+    seller: &UserAccount,
+    price: u64,
+) -> (u64, u64) {
+    set_current_sender(None);
+    let (_, _, token_id) = cep78::mint(context, cep78_hash, seller.key());
+    let post_purse = new_purse(context, seller.address, "post_purse", U512::from(0));
+
+    set_current_sender(seller.address);
+    cep78::approve(context, cep78_hash, token_id, marketplace_hash.into());
+    let post_id = cep82::marketplace::post(
+        context,
+        marketplace_hash,
+        cep78_package,
+        token_id,
+        price.into(),
+        post_purse,
+        None,
+        None,
+    );
+
+    (token_id, post_id)
+}
+
+#[test]
+fn upgrade_keeps_marketplace_state() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+
+    // the first release didn't store the package hash, so the upgrade is given it
+    let (v0_hash, marketplace_package) =
+        deploy_cep82_marketplace_v0(&mut context.builder, context.account.address);
+    // a collection without a custodial contract, so that only the marketplace is upgraded
+    let (cep78_hash, cep78_package) =
+        deploy_cep78(&mut context.builder, context.account.address, None);
+    for owner in [seller.key(), buyer.key()] {
+        cep78::register_owner(&mut context, cep78_hash, owner);
+    }
+
+    set_current_sender(None);
+    cep82::marketplace::register_nft(&mut context, v0_hash, cep78_package, None);
+    let (token1, post1) = mint_and_post_plain(
+        &mut context,
+        v0_hash,
+        cep78_hash,
+        cep78_package,
+        &seller,
+        1_000_000,
+    );
+
+    let (marketplace_hash, upgraded_package) = upgrade_cep82_marketplace(
+        &mut context.builder,
+        context.account.address,
+        marketplace_package,
+    );
+    assert_eq!(upgraded_package, marketplace_package);
+    assert_ne!(marketplace_hash, v0_hash);
+
+    // the hash of the first version is kept next to the new one
+    let installer = context
+        .builder
+        .query(None, Key::Account(context.account.address), &[])
+        .unwrap();
+    let named_keys = installer.as_account().unwrap().named_keys();
+    assert_eq!(
+        named_keys.get("cep82_marketplace"),
+        Some(&Key::from(v0_hash))
+    );
+    assert_eq!(
+        named_keys.get("cep82_marketplace_v2"),
+        Some(&Key::from(marketplace_hash))
+    );
+
+    // the listing of the first release is still in its old layout, until it's rewritten
+    assert_eq!(
+        cep82::marketplace::migrate_listings(&mut context, marketplace_hash, 0, 10),
+        1
+    );
+    assert_eq!(
+        cep82::marketplace::migrate_listings(&mut context, marketplace_hash, 0, 10),
+        0
+    );
+
+    // it predates the listing indexes, and was added to them by the migration
+    let listings =
+        cep82::marketplace::listings_by_seller(&mut context, marketplace_hash, seller.key(), 0, 10);
+    assert_eq!(listings, vec![post1]);
+
+    // counters live in the package's named keys and survive the upgrade
+    let (token2, post2) = mint_and_post_plain(
+        &mut context,
+        marketplace_hash,
+        cep78_hash,
+        cep78_package,
+        &seller,
+        1_000_000,
+    );
+    assert_eq!(post2, post1 + 1);
+
+    // approvals are given to a contract hash, which changed with the upgrade
+    set_current_sender(seller.address);
+    cep78::approve(&mut context, cep78_hash, token1, marketplace_hash.into());
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    for post_id in [post1, post2] {
+        cep82::marketplace::bid(
+            &mut context,
+            marketplace_hash,
+            post_id,
+            bid_purse,
+            1_000_000.into(),
+        );
+    }
+
+    for token_id in [token1, token2] {
+        let owner = cep78::owner_of(&mut context, cep78_hash, token_id);
+        assert_eq!(owner, buyer.key());
+    }
+}
+
+#[test]
+fn upgrade_keeps_custodial_state() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let (_, cep78_package) = deploy_cep78(&mut context.builder, context.account.address, None);
+    let (_, marketplace_package) =
+        deploy_cep82_marketplace(&mut context.builder, context.account.address);
+
+    // the first release enforced its whitelist with a flag, and didn't store the package hash
+    let (v0_hash, custodial_package) = deploy_cep82_custodial_v0(
+        &mut context.builder,
+        context.account.address,
+        vec![marketplace_package],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(1_000),
+        }],
+        manager.key(),
+    );
+    set_current_sender(None);
+    let royalty = cep82::custodial::calculate_royalty(
+        &mut context,
+        v0_hash,
+        cep78_package,
+        1,
+        1_000_000.into(),
+    );
+    assert_eq!(royalty, U512::from(100_000));
+
+    let (custodial_hash, upgraded_package) = upgrade_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        custodial_package,
+        None,
+    );
+    assert_eq!(upgraded_package, custodial_package);
+    assert_ne!(custodial_hash, v0_hash);
+
+    // the first release didn't know its collection, and wasn't told one
+    assert_eq!(
+        cep82::custodial::collection(&mut context, custodial_hash),
+        None
+    );
+
+    assert_eq!(
+        cep82::custodial::marketplace_mode(&mut context, custodial_hash),
+        MarketplaceMode::Whitelist
+    );
+    let config =
+        cep82::custodial::marketplace_config(&mut context, custodial_hash, marketplace_package);
+    assert!(config.unwrap().whitelisted);
+    assert_eq!(
+        cep82::custodial::calculate_royalty(
+            &mut context,
+            custodial_hash,
+            cep78_package,
+            1,
+            1_000_000.into(),
+        ),
+        royalty
+    );
+
+    // the manager of the first release gets every role of the current one
+    for role in [Role::Admin, Role::Manager, Role::Treasurer, Role::Pauser] {
+        assert!(cep82::custodial::has_role(
+            &mut context,
+            custodial_hash,
+            role,
+            manager.key(),
+        ));
+    }
+}
+
+#[test]
+fn upgraded_custodial_collects_royalties_for_the_collection_given_on_upgrade() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+
+    // the collection is installed without a transfer filter: it would keep calling the first
+    // release by its contract hash, so only the marketplace goes through the upgraded contract
+    let (cep78_hash, cep78_package) =
+        deploy_cep78(&mut context.builder, context.account.address, None);
+    for owner in [seller.key(), buyer.key()] {
+        cep78::register_owner(&mut context, cep78_hash, owner);
+    }
+    let (marketplace_hash, marketplace_package) =
+        deploy_cep82_marketplace(&mut context.builder, context.account.address);
+
+    let (_, custodial_package) = deploy_cep82_custodial_v0(
+        &mut context.builder,
+        context.account.address,
+        vec![marketplace_package],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(1_000),
+        }],
+        manager.key(),
+    );
+    let (custodial_hash, _) = upgrade_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        custodial_package,
+        Some(cep78_package),
+    );
+    set_current_sender(None);
+    assert_eq!(
+        cep82::custodial::collection(&mut context, custodial_hash),
+        Some(cep78_package)
+    );
+
+    cep82::marketplace::register_nft(
+        &mut context,
+        marketplace_hash,
+        cep78_package,
+        Some(custodial_package),
+    );
+    let (token_id, post_id) = mint_and_post_plain(
+        &mut context,
+        marketplace_hash,
+        cep78_hash,
+        cep78_package,
+        &seller,
+        1_000_000,
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    let quote = cep82::marketplace::quote(&mut context, marketplace_hash, post_id);
+    cep82::marketplace::bid(
+        &mut context,
+        marketplace_hash,
+        post_id,
+        bid_purse,
+        quote.total_due,
+    );
+
+    assert_eq!(
+        cep78::owner_of(&mut context, cep78_hash, token_id),
+        buyer.key()
+    );
+    assert_eq!(
+        cep82::custodial::royalty_purse_balance(&mut context, custodial_hash),
+        U512::from(100_000)
+    );
+}
+
+#[test]
+fn upgraded_marketplace_trades_through_a_first_release_custodial() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+
+    let (v0_hash, marketplace_package) =
+        deploy_cep82_marketplace_v0(&mut context.builder, context.account.address);
+    let (custodial_hash, custodial_package) = deploy_cep82_custodial_v0(
+        &mut context.builder,
+        context.account.address,
+        vec![marketplace_package],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(1_000),
+        }],
+        manager.key(),
+    );
+    let (cep78_hash, cep78_package) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(custodial_hash.into()),
+    );
+    for owner in [seller.key(), buyer.key()] {
+        cep78::register_owner(&mut context, cep78_hash, owner);
+    }
+
+    set_current_sender(None);
+    cep82::marketplace::register_nft(
+        &mut context,
+        v0_hash,
+        cep78_package,
+        Some(custodial_package),
+    );
+    let (token1, post1) = mint_and_post_plain(
+        &mut context,
+        v0_hash,
+        cep78_hash,
+        cep78_package,
+        &seller,
+        1_000_000,
+    );
+
+    let (marketplace_hash, _) = upgrade_cep82_marketplace(
+        &mut context.builder,
+        context.account.address,
+        marketplace_package,
+    );
+
+    // the custodial contract has no `settlement_required` to be asked
+    set_current_sender(None);
+    cep82::marketplace::register_legacy_nft(
+        &mut context,
+        marketplace_hash,
+        cep78_package,
+        custodial_package,
+    );
+
+    // approvals are given to a contract hash, which changed with the upgrade
+    set_current_sender(seller.address);
+    cep78::approve(&mut context, cep78_hash, token1, marketplace_hash.into());
+    let (token2, post2) = mint_and_post_plain(
+        &mut context,
+        marketplace_hash,
+        cep78_hash,
+        cep78_package,
+        &seller,
+        1_000_000,
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    for post_id in [post1, post2] {
+        let quote = cep82::marketplace::quote(&mut context, marketplace_hash, post_id);
+        assert_eq!(quote.royalty, U512::from(100_000));
+
+        cep82::marketplace::bid(
+            &mut context,
+            marketplace_hash,
+            post_id,
+            bid_purse,
+            quote.total_due,
+        );
+    }
+
+    for token_id in [token1, token2] {
+        let owner = cep78::owner_of(&mut context, cep78_hash, token_id);
+        assert_eq!(owner, buyer.key());
+    }
+    assert_eq!(
+        cep82::custodial::royalty_purse_balance(&mut context, custodial_hash),
+        U512::from(200_000)
+    );
+}
+
+#[test]
+fn granted_pauser_can_pause_until_revoked() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let pauser = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key()]);
+
+    // the installing account holds every role
+    let installer: Key = context.account.address.into();
+    set_current_sender(None);
+    assert!(cep82::marketplace::has_role(
+        &mut context,
+        market.marketplace_hash,
+        Role::Admin,
+        installer,
+    ));
+    assert!(!cep82::marketplace::has_role(
+        &mut context,
+        market.marketplace_hash,
+        Role::Pauser,
+        pauser.key(),
+    ));
+
+    cep82::marketplace::grant_role(
+        &mut context,
+        market.marketplace_hash,
+        Role::Pauser,
+        pauser.key(),
+    );
+
+    set_current_sender(pauser.address);
+    cep82::marketplace::set_paused(&mut context, market.marketplace_hash, true);
+    assert!(cep82::marketplace::is_paused(
+        &mut context,
+        market.marketplace_hash
+    ));
+
+    set_current_sender(None);
+    cep82::marketplace::revoke_role(
+        &mut context,
+        market.marketplace_hash,
+        Role::Pauser,
+        pauser.key(),
+    );
+    assert!(!cep82::marketplace::has_role(
+        &mut context,
+        market.marketplace_hash,
+        Role::Pauser,
+        pauser.key(),
+    ));
+}
+
+#[test]
+fn error_codes_decode_to_their_variant() {
+    use contract_common::{
+        error::{CommonError, UserError},
+        ext::cep78::Cep78Error,
+    };
+    use contract_custodial::CustodialError;
+    use contract_marketplace::MarketError;
+
+    assert_eq!(
+        util::errors::describe(MarketError::StaleListing.code()),
+        Some(("MarketError", "StaleListing"))
+    );
+    assert_eq!(
+        util::errors::describe(CustodialError::AlreadyPaid.code()),
+        Some(("CustodialError", "AlreadyPaid"))
+    );
+    assert_eq!(
+        util::errors::describe(CommonError::ContractPaused.code()),
+        Some(("CommonError", "ContractPaused"))
+    );
+
+    // the first variant of each enum no longer shares code 0
+    assert_ne!(
+        MarketError::InvalidMethodAccess.code(),
+        CustodialError::MarketplaceNotWhitelisted.code()
+    );
+
+    // CEP-78 and ERC-20 codes lie outside of every range
+    assert_eq!(
+        util::errors::describe(Cep78Error::InvalidTokenIdentifier.into()),
+        Some(("Cep78Error", "InvalidTokenIdentifier"))
+    );
+    assert_eq!(util::errors::describe(u16::MAX), None);
+}
+
+#[test]
+fn error_enums_claim_distinct_ranges() {
+    use contract_common::error::{
+        CallStackError, CommonError, ErrorRange, UserError, ERROR_RANGE_SIZE,
+    };
+    use contract_custodial::CustodialError;
+    use contract_marketplace::MarketError;
+
+    let enums = [
+        (CommonError::RANGE, CommonError::VARIANTS.len()),
+        (CallStackError::RANGE, CallStackError::VARIANTS.len()),
+        (CustodialError::RANGE, CustodialError::VARIANTS.len()),
+        (MarketError::RANGE, MarketError::VARIANTS.len()),
+    ];
+
+    for (i, (range, variants)) in enums.iter().enumerate() {
+        assert!(
+            enums[..i].iter().all(|(other, _)| other != range),
+            "{range:?} is claimed twice"
+        );
+        assert!(*variants <= usize::from(ERROR_RANGE_SIZE));
+        assert_eq!(ErrorRange::of(range.base()), Some(*range));
+    }
+
+    // CEP-78 counts up from 1 and ERC-20 down from `u16::MAX`
+    for code in [
+        1,
+        ERROR_RANGE_SIZE - 1,
+        u16::MAX - ERROR_RANGE_SIZE + 1,
+        u16::MAX,
+    ] {
+        assert_eq!(ErrorRange::of(code), None);
+    }
+}
+
+#[test]
+fn cep78_issuer_and_transfer_filter_are_queryable() {
+    let mut context = setup_context();
+
+    let owner = UserAccount::unique_account(&mut context, 10);
+    let (custodial_hash, _) = deploy_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        vec![],
+        RoyaltyStep::basic(),
+        owner.key(),
+    );
+    let (cep78_hash, _) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(custodial_hash.into()),
+    );
+    let (plain_cep78_hash, _) = deploy_cep78(&mut context.builder, context.account.address, None);
+
+    cep78::register_owner(&mut context, cep78_hash, owner.key());
+    set_current_sender(None);
+    let (_, _, token_id) = cep78::mint(&mut context, cep78_hash, owner.key());
+
+    assert_eq!(
+        cep78::token_issuer(&mut context, cep78_hash, token_id),
+        context.account.key()
+    );
+    assert_eq!(
+        cep78::transfer_filter_contract(&mut context, cep78_hash),
+        Some(custodial_hash.into())
+    );
+    assert_eq!(
+        cep78::transfer_filter_contract(&mut context, plain_cep78_hash),
+        None
+    );
+}
+
+#[test]
+fn ext_cep78_drives_a_collection_from_a_contract() {
+    let mut context = setup_context();
+
+    let spender = UserAccount::unique_account(&mut context, 10);
+    let client = install_cep78_client(&mut context);
+    let client_key = Key::from(client);
+    let (cep78_hash, cep78_package) =
+        deploy_cep78_with_public_minting(&mut context.builder, context.account.address);
+    cep78::register_owner(&mut context, cep78_hash, client_key);
+
+    let token_id = cep78::client::mint(&mut context, client, cep78_package);
+    assert_eq!(
+        cep78::owner_of(&mut context, cep78_hash, token_id),
+        client_key
+    );
+    assert_eq!(
+        cep78::client::balance_of(&mut context, client, cep78_package, client_key),
+        1
+    );
+    // the collection stores the metadata reformatted, so only its contents are compared
+    let metadata = cep78::client::metadata(&mut context, client, cep78_package, token_id);
+    assert!(metadata.contains("John Doe"));
+
+    // the issuers are read straight from the collection's dictionary
+    let token_issuers = cep78::token_issuers_address(&mut context, cep78_hash);
+    assert_eq!(
+        cep78::client::token_issuer(&mut context, client, token_issuers, token_id),
+        Some(client_key)
+    );
+    assert_eq!(
+        cep78::client::token_issuer(&mut context, client, token_issuers, token_id + 1),
+        None
+    );
+
+    let is_approved = |context: &mut TestContext| {
+        cep78::client::is_approved(
+            context,
+            client,
+            cep78_package,
+            token_id,
+            client_key,
+            spender.key(),
+        )
+    };
+    cep78::client::approve(&mut context, client, cep78_package, token_id, spender.key());
+    assert!(is_approved(&mut context));
+    cep78::client::revoke(&mut context, client, cep78_package, token_id);
+    assert!(!is_approved(&mut context));
+
+    cep78::client::set_approval_for_all(&mut context, client, cep78_package, true, spender.key());
+    assert!(cep78::client::is_approved_for_all(
+        &mut context,
+        client,
+        cep78_package,
+        client_key,
+        spender.key(),
+    ));
+    assert!(is_approved(&mut context));
+
+    cep78::client::burn(&mut context, client, cep78_package, token_id);
+    assert_eq!(
+        cep78::client::balance_of(&mut context, client, cep78_package, client_key),
+        0
+    );
+}
+
+/// Mint a token to `seller`, make the marketplace an operator for all of the seller's tokens and
+/// list the token.
+fn mint_and_post_as_operator(
+    context: &mut TestContext,
+    market: &Market,
+    seller: &UserAccount,
+) -> (u64, u64) {
+    set_current_sender(None);
+    let (_, _, token_id) = cep78::mint(context, market.cep78_hash, seller.key());
+    let post_purse = new_purse(context, seller.address, "post_purse", U512::from(0));
+
+    set_current_sender(seller.address);
+    cep78::set_approval_for_all(
+        context,
+        market.cep78_hash,
+        true,
+        market.marketplace_hash.into(),
+    );
+    let post_id = cep82::marketplace::post(
+        context,
+        market.marketplace_hash,
+        market.cep78_package,
+        token_id,
+        1_000_000.into(),
+        post_purse,
+        None,
+        None,
+    );
+
+    (token_id, post_id)
+}
+
+#[test]
+fn operator_approval_is_enough_to_post_and_sell() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (token_id, post_id) = mint_and_post_as_operator(&mut context, &market, &seller);
+
+    // the custodial contract accepts the operator approval when the royalty is paid
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+
+    let owner = cep78::owner_of(&mut context, market.cep78_hash, token_id);
+    assert_eq!(owner, buyer.key());
+}
+
+#[test]
+fn revoked_operator_approval_makes_listing_stale() {
+    use contract_marketplace::MarketError;
+
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (_, post_id) = mint_and_post_as_operator(&mut context, &market, &seller);
+
+    set_current_sender(seller.address);
+    cep78::set_approval_for_all(
+        &mut context,
+        market.cep78_hash,
+        false,
+        market.marketplace_hash.into(),
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    expect_revert(&mut context, MarketError::StaleListing, |context| {
+        cep82::marketplace::bid(
+            context,
+            market.marketplace_hash,
+            post_id,
+            bid_purse,
+            1_000_000.into(),
+        )
+    });
+}
+
+#[test]
+fn owner_transfers_directly_paying_the_transfer_fee() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let friend = UserAccount::unique_account(&mut context, 30);
+
+    let (custodial_hash, custodial_package) = deploy_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        vec![],
+        RoyaltyStep::basic(),
+        manager.key(),
+    );
+    let (cep78_hash, cep78_package) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(custodial_hash.into()),
+    );
+
+    cep78::register_owner(&mut context, cep78_hash, owner.key());
+    cep78::register_owner(&mut context, cep78_hash, friend.key());
+    set_current_sender(None);
+    let (_, _, token_id) = cep78::mint(&mut context, cep78_hash, owner.key());
+
+    // direct transfers are disabled until the manager sets a fee, which may be zero
+    set_current_sender(manager.address);
+    cep82::custodial::set_collection(&mut context, custodial_hash, cep78_package);
+    assert_eq!(
+        cep82::custodial::transfer_fee(&mut context, custodial_hash),
+        None
+    );
+    cep82::custodial::set_transfer_fee(&mut context, custodial_hash, Some(U512::zero()));
+
+    cep82::custodial::transfer_directly(
+        &mut context,
+        owner.address,
+        custodial_package,
+        cep78_package,
+        token_id,
+        friend.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, cep78_hash, token_id),
+        friend.key()
+    );
+    assert_eq!(
+        cep82::custodial::royalty_purse_balance(&mut context, custodial_hash),
+        U512::zero()
+    );
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_transfer_fee(&mut context, custodial_hash, Some(U512::from(1_000)));
+    assert_eq!(
+        cep82::custodial::transfer_fee(&mut context, custodial_hash),
+        Some(U512::from(1_000))
+    );
+
+    cep82::custodial::transfer_directly(
+        &mut context,
+        friend.address,
+        custodial_package,
+        cep78_package,
+        token_id,
+        owner.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, cep78_hash, token_id),
+        owner.key()
+    );
+    assert_eq!(
+        cep82::custodial::royalty_purse_balance(&mut context, custodial_hash),
+        U512::from(1_000)
+    );
+}
+
+struct CustodialCollection {
+    custodial_hash: ContractHash,
+    custodial_package: ContractPackageHash,
+    cep78_hash: ContractHash,
+    cep78_package: ContractPackageHash,
+    token_ids: Vec<u64>,
+}
+
+/// Deploy a custodial contract managed by `manager` and a collection it filters, and mint a token
+/// to each of `owners`. Direct transfers are enabled, for free.
+fn setup_custodial_collection(
+    context: &mut TestContext,
+    manager: Key,
+    owners: &[Key],
+) -> CustodialCollection {
+    let (custodial_hash, custodial_package) = deploy_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        vec![],
+        RoyaltyStep::basic(),
+        manager,
+    );
+    let (cep78_hash, cep78_package) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(custodial_hash.into()),
+    );
+
+    for (i, owner) in owners.iter().enumerate() {
+        if !owners[..i].contains(owner) {
+            cep78::register_owner(context, cep78_hash, *owner);
+        }
+    }
+
+    set_current_sender(manager.into_account());
+    cep82::custodial::set_collection(context, custodial_hash, cep78_package);
+    cep82::custodial::set_transfer_fee(context, custodial_hash, Some(U512::zero()));
+
+    set_current_sender(None);
+    let token_ids = owners
+        .iter()
+        .map(|owner| cep78::mint(context, cep78_hash, *owner).2)
+        .collect();
+
+    CustodialCollection {
+        custodial_hash,
+        custodial_package,
+        cep78_hash,
+        cep78_package,
+        token_ids,
+    }
+}
+
+#[test]
+fn manager_allowance_lets_one_transfer_through_without_royalty() {
+    use casper_types::bytesrepr::FromBytes;
+    use contract_common::{events::Event, ext::cep78::Cep78Error};
+    use contract_custodial::events::RoyaltyExemptTransfer;
+
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let friend = UserAccount::unique_account(&mut context, 30);
+    let CustodialCollection {
+        custodial_hash,
+        cep78_hash,
+        token_ids,
+        ..
+    } = setup_custodial_collection(&mut context, manager.key(), &[owner.key(), friend.key()]);
+    let token_id = token_ids[0];
+
+    set_current_sender(manager.address);
+    cep82::custodial::allow_transfer(
+        &mut context,
+        custodial_hash,
+        token_id,
+        owner.key(),
+        friend.key(),
+    );
+
+    set_current_sender(owner.address);
+    cep78::transfer(
+        &mut context,
+        cep78_hash,
+        token_id,
+        owner.key(),
+        friend.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, cep78_hash, token_id),
+        friend.key()
+    );
+
+    let record = cep82::custodial::event(&mut context, custodial_hash, 0);
+    assert_eq!(record.name, RoyaltyExemptTransfer::NAME);
+    let (event, _) = RoyaltyExemptTransfer::from_bytes(&record.data).unwrap();
+    assert_eq!(event.source_key, owner.key());
+    assert_eq!(event.target_key, friend.key());
+    assert_eq!(event.granted_by, manager.key());
+
+    // the allowance only covers that one transfer
+    set_current_sender(friend.address);
+    expect_revert(
+        &mut context,
+        Cep78Error::TransferFilterContractDenied,
+        |context| cep78::transfer(context, cep78_hash, token_id, friend.key(), owner.key()),
+    );
+}
+
+#[test]
+fn royalty_exemption_is_used_up_by_one_transfer() {
+    use contract_common::ext::cep78::Cep78Error;
+
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let friend = UserAccount::unique_account(&mut context, 30);
+    let CustodialCollection {
+        custodial_hash,
+        cep78_hash,
+        token_ids,
+        ..
+    } = setup_custodial_collection(
+        &mut context,
+        manager.key(),
+        &[owner.key(), owner.key(), friend.key()],
+    );
+
+    set_current_sender(manager.address);
+    cep82::custodial::exempt_from_royalty(&mut context, custodial_hash, owner.key(), u64::MAX);
+
+    set_current_sender(owner.address);
+    cep78::transfer(
+        &mut context,
+        cep78_hash,
+        token_ids[0],
+        owner.key(),
+        friend.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, cep78_hash, token_ids[0]),
+        friend.key()
+    );
+
+    expect_revert(
+        &mut context,
+        Cep78Error::TransferFilterContractDenied,
+        |context| cep78::transfer(context, cep78_hash, token_ids[1], owner.key(), friend.key()),
+    );
+}
+
+#[test]
+fn allow_list_limits_who_can_receive_tokens() {
+    use contract_custodial::CustodialError;
+
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let friend = UserAccount::unique_account(&mut context, 30);
+    let stranger = UserAccount::unique_account(&mut context, 40);
+    let collection = setup_custodial_collection(&mut context, manager.key(), &[owner.key()]);
+    let custodial_hash = collection.custodial_hash;
+    let token_id = collection.token_ids[0];
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_recipient_list(&mut context, custodial_hash, Some(RecipientList::Allow));
+    cep82::custodial::add_recipients(
+        &mut context,
+        custodial_hash,
+        RecipientList::Allow,
+        vec![friend.key(), owner.key()],
+    );
+    assert_eq!(
+        cep82::custodial::recipient_list(&mut context, custodial_hash),
+        Some(RecipientList::Allow)
+    );
+    assert!(cep82::custodial::is_recipient_listed(
+        &mut context,
+        custodial_hash,
+        RecipientList::Allow,
+        friend.key()
+    ));
+    assert!(!cep82::custodial::can_receive(
+        &mut context,
+        custodial_hash,
+        stranger.key()
+    ));
+
+    expect_revert(
+        &mut context,
+        CustodialError::RecipientNotAllowed,
+        |context| {
+            cep82::custodial::transfer_directly(
+                context,
+                owner.address,
+                collection.custodial_package,
+                collection.cep78_package,
+                token_id,
+                stranger.key(),
+            )
+        },
+    );
+
+    cep82::custodial::transfer_directly(
+        &mut context,
+        owner.address,
+        collection.custodial_package,
+        collection.cep78_package,
+        token_id,
+        friend.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, collection.cep78_hash, token_id),
+        friend.key()
+    );
+
+    set_current_sender(manager.address);
+    cep82::custodial::remove_recipients(
+        &mut context,
+        custodial_hash,
+        RecipientList::Allow,
+        vec![friend.key()],
+    );
+    assert!(!cep82::custodial::can_receive(
+        &mut context,
+        custodial_hash,
+        friend.key()
+    ));
+
+    // lifting the list lets anyone receive again, and keeps its entries
+    cep82::custodial::set_recipient_list(&mut context, custodial_hash, None);
+    assert!(cep82::custodial::can_receive(
+        &mut context,
+        custodial_hash,
+        stranger.key()
+    ));
+    assert!(cep82::custodial::is_recipient_listed(
+        &mut context,
+        custodial_hash,
+        RecipientList::Allow,
+        owner.key()
+    ));
+}
+
+#[test]
+fn deny_list_blocks_exempt_transfers_to_listed_recipients() {
+    use contract_common::ext::cep78::Cep78Error;
+
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let stranger = UserAccount::unique_account(&mut context, 30);
+    let collection = setup_custodial_collection(&mut context, manager.key(), &[owner.key()]);
+    let custodial_hash = collection.custodial_hash;
+    let token_id = collection.token_ids[0];
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_recipient_list(&mut context, custodial_hash, Some(RecipientList::Deny));
+    cep82::custodial::add_recipients(
+        &mut context,
+        custodial_hash,
+        RecipientList::Deny,
+        vec![stranger.key()],
+    );
+    cep82::custodial::allow_transfer(
+        &mut context,
+        custodial_hash,
+        token_id,
+        owner.key(),
+        stranger.key(),
+    );
+
+    // the recipient check comes on top of the royalty check, so an allowance doesn't bypass it
+    set_current_sender(owner.address);
+    expect_revert(
+        &mut context,
+        Cep78Error::TransferFilterContractDenied,
+        |context| {
+            cep78::transfer(
+                context,
+                collection.cep78_hash,
+                token_id,
+                owner.key(),
+                stranger.key(),
+            )
+        },
+    );
+}
+
+#[test]
+fn transfer_lock_holds_every_token_until_it_ends() {
+    use contract_custodial::CustodialError;
+
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let friend = UserAccount::unique_account(&mut context, 30);
+    let collection = setup_custodial_collection(&mut context, manager.key(), &[owner.key()]);
+    let custodial_hash = collection.custodial_hash;
+    let token_id = collection.token_ids[0];
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_transfer_lock(&mut context, custodial_hash, 1_000);
+    assert_eq!(
+        cep82::custodial::transferable_at(&mut context, custodial_hash, token_id),
+        1_000
+    );
+
+    context.block_time = 999;
+    expect_revert(&mut context, CustodialError::TransferLocked, |context| {
+        cep82::custodial::transfer_directly(
+            context,
+            owner.address,
+            collection.custodial_package,
+            collection.cep78_package,
+            token_id,
+            friend.key(),
+        )
+    });
+
+    context.block_time = 1_000;
+    cep82::custodial::transfer_directly(
+        &mut context,
+        owner.address,
+        collection.custodial_package,
+        collection.cep78_package,
+        token_id,
+        friend.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, collection.cep78_hash, token_id),
+        friend.key()
+    );
+}
+
+#[test]
+fn cooldown_delays_the_next_transfer_of_a_token() {
+    use contract_custodial::CustodialError;
+
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let friend = UserAccount::unique_account(&mut context, 30);
+    let collection =
+        setup_custodial_collection(&mut context, manager.key(), &[owner.key(), friend.key()]);
+    let custodial_hash = collection.custodial_hash;
+    let token_id = collection.token_ids[0];
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_transfer_cooldown(&mut context, custodial_hash, 500);
+    assert_eq!(
+        cep82::custodial::transferable_at(&mut context, custodial_hash, token_id),
+        0
+    );
+
+    context.block_time = 100;
+    cep82::custodial::transfer_directly(
+        &mut context,
+        owner.address,
+        collection.custodial_package,
+        collection.cep78_package,
+        token_id,
+        friend.key(),
+    );
+    assert_eq!(
+        cep82::custodial::transferable_at(&mut context, custodial_hash, token_id),
+        600
+    );
+
+    // the cooldown is per token, so the friend's own token isn't held back
+    cep82::custodial::transfer_directly(
+        &mut context,
+        friend.address,
+        collection.custodial_package,
+        collection.cep78_package,
+        collection.token_ids[1],
+        owner.key(),
+    );
+
+    context.block_time = 599;
+    expect_revert(&mut context, CustodialError::TransferLocked, |context| {
+        cep82::custodial::transfer_directly(
+            context,
+            friend.address,
+            collection.custodial_package,
+            collection.cep78_package,
+            token_id,
+            owner.key(),
+        )
+    });
+
+    context.block_time = 600;
+    cep82::custodial::transfer_directly(
+        &mut context,
+        friend.address,
+        collection.custodial_package,
+        collection.cep78_package,
+        token_id,
+        owner.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, collection.cep78_hash, token_id),
+        owner.key()
+    );
+}
+
+/// Deploy a custodial collection that tokens are minted into and claimed from. The installing
+/// account is the manager of the custodial contract.
+fn setup_custody(context: &mut TestContext, owners: &[Key]) -> CustodialCollection {
+    let manager = context.account.key();
+    let collection = setup_custodial_collection(context, manager, &[]);
+
+    cep78::register_owner(
+        context,
+        collection.cep78_hash,
+        collection.custodial_package.into(),
+    );
+    for owner in owners {
+        cep78::register_owner(context, collection.cep78_hash, *owner);
+    }
+
+    collection
+}
+
+#[test]
+fn claim_hands_custodied_tokens_to_their_owners() {
+    use contract_common::error::CommonError;
+    use contract_custodial::CustodialError;
+
+    let mut context = setup_context();
+
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let stranger = UserAccount::unique_account(&mut context, 30);
+    let collection = setup_custody(&mut context, &[owner.key(), stranger.key()]);
+    assert_eq!(
+        cep82::custodial::collection(&mut context, collection.custodial_hash),
+        Some(collection.cep78_package)
+    );
+
+    let (_, _, token_id) = cep82::custodial::mint_and_claim(
+        &mut context,
+        collection.cep78_hash,
+        collection.custodial_hash,
+        collection.custodial_package,
+        owner.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, collection.cep78_hash, token_id),
+        owner.key()
+    );
+
+    // the token has left custody, so it can't be claimed again
+    expect_revert(&mut context, CustodialError::SourceMustBeOwner, |context| {
+        cep82::custodial::claim(context, collection.custodial_hash, token_id, stranger.key())
+    });
+
+    let (_, _, token_id) = cep78::mint(
+        &mut context,
+        collection.cep78_hash,
+        collection.custodial_package.into(),
+    );
+    set_current_sender(stranger.address);
+    expect_revert(
+        &mut context,
+        CustodialError::CallerMustBeClaimant,
+        |context| {
+            cep82::custodial::claim(context, collection.custodial_hash, token_id, stranger.key())
+        },
+    );
+    expect_revert(&mut context, CommonError::MissingRole, |context| {
+        cep82::custodial::set_claimant(
+            context,
+            collection.custodial_hash,
+            token_id,
+            Some(stranger.key()),
+        )
+    });
+
+    // once a manager records who a token is for, they can claim it themselves
+    set_current_sender(None);
+    cep82::custodial::set_claimant(
+        &mut context,
+        collection.custodial_hash,
+        token_id,
+        Some(stranger.key()),
+    );
+    set_current_sender(stranger.address);
+    expect_revert(
+        &mut context,
+        CustodialError::CallerMustBeClaimant,
+        |context| {
+            cep82::custodial::claim(context, collection.custodial_hash, token_id, owner.key())
+        },
+    );
+    cep82::custodial::claim(
+        &mut context,
+        collection.custodial_hash,
+        token_id,
+        stranger.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, collection.cep78_hash, token_id),
+        stranger.key()
+    );
+    assert_eq!(
+        cep82::custodial::claimant(&mut context, collection.custodial_hash, token_id),
+        None
+    );
+}
+
+#[test]
+fn delegate_marketplace_is_the_only_way_to_move_a_token() {
+    use contract_custodial::CustodialError;
+
+    let mut context = setup_context();
+
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let collection = setup_custody(&mut context, &[owner.key(), buyer.key()]);
+    let custodial_hash = collection.custodial_hash;
+    let cep78_hash = collection.cep78_hash;
+    let cep78_package = collection.cep78_package;
+
+    let (delegate_hash, delegate_package) =
+        deploy_cep82_marketplace(&mut context.builder, context.account.address);
+    let (other_hash, _) = deploy_cep82_marketplace(&mut context.builder, context.account.address);
+    for marketplace in [delegate_hash, other_hash] {
+        cep82::marketplace::register_nft(
+            &mut context,
+            marketplace,
+            cep78_package,
+            Some(collection.custodial_package),
+        );
+    }
+
+    let (_, _, token_id) = cep82::custodial::mint_and_claim(
+        &mut context,
+        cep78_hash,
+        custodial_hash,
+        collection.custodial_package,
+        owner.key(),
+    );
+
+    set_current_sender(owner.address);
+    cep82::custodial::set_delegate(
+        &mut context,
+        custodial_hash,
+        token_id,
+        Some(delegate_package),
+    );
+    assert_eq!(
+        cep82::custodial::delegate(&mut context, custodial_hash, token_id),
+        Some(delegate_package)
+    );
+
+    expect_revert(
+        &mut context,
+        CustodialError::CallerMustBeDelegate,
+        |context| {
+            cep82::custodial::transfer_directly(
+                context,
+                owner.address,
+                collection.custodial_package,
+                cep78_package,
+                token_id,
+                buyer.key(),
+            )
+        },
+    );
+
+    let post_purse = new_purse(&mut context, owner.address, "post_purse", U512::from(0));
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+
+    cep78::approve(&mut context, cep78_hash, token_id, other_hash.into());
+    let post_id = cep82::marketplace::post(
+        &mut context,
+        other_hash,
+        cep78_package,
+        token_id,
+        1_000_000.into(),
+        post_purse,
+        None,
+        None,
+    );
+    set_current_sender(buyer.address);
+    expect_revert(
+        &mut context,
+        CustodialError::CallerMustBeDelegate,
+        |context| {
+            cep82::marketplace::bid(context, other_hash, post_id, bid_purse, 1_000_000.into())
+        },
+    );
+
+    set_current_sender(owner.address);
+    cep78::approve(&mut context, cep78_hash, token_id, delegate_hash.into());
+    let post_id = cep82::marketplace::post(
+        &mut context,
+        delegate_hash,
+        cep78_package,
+        token_id,
+        1_000_000.into(),
+        post_purse,
+        None,
+        None,
+    );
+    set_current_sender(buyer.address);
+    cep82::marketplace::bid(
+        &mut context,
+        delegate_hash,
+        post_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+
+    assert_eq!(
+        cep78::owner_of(&mut context, cep78_hash, token_id),
+        buyer.key()
+    );
+    // the delegate was the seller's choice, so the sale clears it
+    assert_eq!(
+        cep82::custodial::delegate(&mut context, custodial_hash, token_id),
+        None
+    );
+}
+
+#[test]
+fn settlement_splits_the_total_due() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market_with(
+        &mut context,
+        manager.key(),
+        &[seller.key(), buyer.key()],
+        vec![],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(1_000),
+        }],
+    );
+
+    require_settlement(&mut context, &market, &manager);
+
+    let (token_id, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+    let post_purse = context
+        .builder
+        .get_account(seller.address)
+        .unwrap()
+        .named_keys()["post_purse"]
+        .into_uref()
+        .unwrap();
+
+    // the buyer offers more than the price, but only the price is handed to the custodial
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post_id,
+        bid_purse,
+        1_200_000.into(),
+    );
+
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, token_id),
+        buyer.key()
+    );
+    assert_eq!(
+        cep82::custodial::royalty_purse_balance(&mut context, market.custodial_hash),
+        U512::from(100_000)
+    );
+    assert_eq!(
+        context.builder.get_purse_balance(post_purse),
+        U512::from(900_000)
+    );
+    assert_eq!(
+        context.builder.get_purse_balance(bid_purse),
+        U512::from(9_000_000)
+    );
+}
+
+#[test]
+fn royalty_is_calculated_on_at_least_the_floor_value() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market_with(
+        &mut context,
+        manager.key(),
+        &[seller.key(), buyer.key()],
+        vec![],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(1_000),
+        }],
+    );
+    let custodial_hash = market.custodial_hash;
+
+    let (token_id, post_id) = mint_and_post(&mut context, &market, &seller, 2_000_000, None, None);
+    let royalty_at = |context: &mut TestContext, payment_amount: u64| {
+        cep82::custodial::calculate_royalty(
+            context,
+            custodial_hash,
+            market.cep78_package,
+            token_id,
+            payment_amount.into(),
+        )
+    };
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_floor_value(&mut context, custodial_hash, 1_000_000.into());
+    assert_eq!(royalty_at(&mut context, 100), U512::from(100_000));
+    assert_eq!(royalty_at(&mut context, 3_000_000), U512::from(300_000));
+
+    // a token's own floor takes precedence over the collection's, even when it's lower
+    cep82::custodial::set_token_floor_value(
+        &mut context,
+        custodial_hash,
+        token_id,
+        Some(500_000.into()),
+    );
+    assert_eq!(
+        cep82::custodial::floor_value(&mut context, custodial_hash, token_id),
+        U512::from(500_000)
+    );
+    assert_eq!(royalty_at(&mut context, 100), U512::from(50_000));
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post_id,
+        bid_purse,
+        2_000_000.into(),
+    );
+
+    // the sale price was recorded, and counts once the floor follows sales
+    set_current_sender(manager.address);
+    assert_eq!(royalty_at(&mut context, 100), U512::from(50_000));
+    cep82::custodial::set_floor_follows_last_sale(&mut context, custodial_hash, true);
+    assert_eq!(
+        cep82::custodial::floor_value(&mut context, custodial_hash, token_id),
+        U512::from(2_000_000)
+    );
+    assert_eq!(royalty_at(&mut context, 100), U512::from(200_000));
+
+    cep82::custodial::set_token_floor_value(&mut context, custodial_hash, token_id, None);
+    cep82::custodial::set_floor_follows_last_sale(&mut context, custodial_hash, false);
+    assert_eq!(
+        cep82::custodial::floor_value(&mut context, custodial_hash, token_id),
+        U512::from(1_000_000)
+    );
+}
+
+#[test]
+fn partner_marketplace_gets_its_own_royalty_and_a_rebate() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let partner = UserAccount::unique_account(&mut context, 40);
+    let market = setup_market_with(
+        &mut context,
+        manager.key(),
+        &[seller.key(), buyer.key()],
+        vec![],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(1_000),
+        }],
+    );
+
+    let config = MarketplaceConfig {
+        whitelisted: true,
+        royalty_structure: Some(vec![RoyaltyStep::Percentage {
+            percent: U256::from(500),
+        }]),
+        rebate_bps: 2_000,
+        rebate_account: Some(partner.address),
+    };
+    set_current_sender(manager.address);
+    cep82::custodial::set_marketplace_config(
+        &mut context,
+        market.custodial_hash,
+        market.marketplace_package,
+        Some(config.clone()),
+    );
+    assert_eq!(
+        cep82::custodial::marketplace_config(
+            &mut context,
+            market.custodial_hash,
+            market.marketplace_package
+        ),
+        Some(config)
+    );
+
+    let (token_id, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    // only sales by the partner get its royalty structure, and its quotes reflect that
+    assert_eq!(
+        cep82::custodial::calculate_royalty(
+            &mut context,
+            market.custodial_hash,
+            market.cep78_package,
+            token_id,
+            1_000_000.into(),
+        ),
+        U512::from(100_000)
+    );
+    assert_eq!(
+        cep82::marketplace::quote(&mut context, market.marketplace_hash, post_id).royalty,
+        U512::from(50_000)
+    );
+
+    let partner_purse = context
+        .builder
+        .get_account(partner.address)
+        .unwrap()
+        .main_purse();
+    let partner_balance = context.builder.get_purse_balance(partner_purse);
+
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+
+    assert_eq!(
+        cep82::custodial::royalty_purse_balance(&mut context, market.custodial_hash),
+        U512::from(40_000)
+    );
+    assert_eq!(
+        context.builder.get_purse_balance(partner_purse),
+        partner_balance + U512::from(10_000)
+    );
+}
+
+#[test]
+fn marketplace_mode_can_be_switched_after_install() {
+    use contract_custodial::CustodialError;
+
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market(&mut context, manager.key(), &[seller.key(), buyer.key()]);
+    let custodial_hash = market.custodial_hash;
+
+    // an empty whitelist at install leaves the contract open
+    assert_eq!(
+        cep82::custodial::marketplace_mode(&mut context, custodial_hash),
+        MarketplaceMode::Open
+    );
+
+    set_current_sender(manager.address);
+    cep82::custodial::add_denied_marketplaces(
+        &mut context,
+        custodial_hash,
+        vec![market.marketplace_package],
+    );
+    cep82::custodial::set_marketplace_mode(&mut context, custodial_hash, MarketplaceMode::Denylist);
+    assert!(cep82::custodial::is_marketplace_denied(
+        &mut context,
+        custodial_hash,
+        market.marketplace_package
+    ));
+
+    let (token_id, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+    set_current_sender(buyer.address);
+    let bid_purse = new_purse(
+        &mut context,
+        buyer.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    );
+    let bid = |context: &mut TestContext| {
+        cep82::marketplace::bid(
+            context,
+            market.marketplace_hash,
+            post_id,
+            bid_purse,
+            1_000_000.into(),
+        )
+    };
+    expect_revert(&mut context, CustodialError::MarketplaceDenied, bid);
+
+    // off the deny-list, but in whitelist mode the marketplace also has to be whitelisted
+    set_current_sender(manager.address);
+    cep82::custodial::remove_denied_marketplaces(
+        &mut context,
+        custodial_hash,
+        vec![market.marketplace_package],
+    );
+    cep82::custodial::set_marketplace_mode(
+        &mut context,
+        custodial_hash,
+        MarketplaceMode::Whitelist,
+    );
+    set_current_sender(buyer.address);
+    expect_revert(&mut context, CustodialError::MarketplaceNotWhitelisted, bid);
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_marketplace_config(
+        &mut context,
+        custodial_hash,
+        market.marketplace_package,
+        Some(MarketplaceConfig {
+            whitelisted: true,
+            ..MarketplaceConfig::default()
+        }),
+    );
+    set_current_sender(buyer.address);
+    bid(&mut context);
+
+    assert_eq!(
+        cep78::owner_of(&mut context, market.cep78_hash, token_id),
+        buyer.key()
+    );
+}
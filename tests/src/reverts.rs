@@ -0,0 +1,1142 @@
+//! Failure paths: every error variant of the marketplace and custodial contracts that a deploy can
+//! run into. The custodial checks that only a misbehaving marketplace could trip are driven through
+//! the test utility contract.
+
+use casper_types::runtime_args;
+use contract_common::{error::CommonError, ext::cep78::Cep78Error};
+use contract_custodial::CustodialError;
+use contract_marketplace::MarketError;
+
+use super::*;
+use crate::util::deploy::deploy_cep82_marketplace_with_fee;
+
+fn funded_purse(context: &mut TestContext, account: &UserAccount) -> casper_types::URef {
+    new_purse(
+        context,
+        account.address,
+        "bid_purse",
+        U512::from(10_000_000),
+    )
+}
+
+/// Mint a token to `owner` and approve the marketplace for it.
+fn mint_approved(context: &mut TestContext, market: &Market, owner: &UserAccount) -> u64 {
+    set_current_sender(None);
+    let (_, _, token_id) = cep78::mint(context, market.cep78_hash, owner.key());
+
+    set_current_sender(owner.address);
+    cep78::approve(
+        context,
+        market.cep78_hash,
+        token_id,
+        market.marketplace_hash.into(),
+    );
+
+    token_id
+}
+
+#[test]
+fn cancel_by_another_account_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let other = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key()]);
+
+    let (_, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    set_current_sender(other.address);
+    expect_revert(&mut context, MarketError::InvalidMethodAccess, |context| {
+        cep82::marketplace::cancel(context, market.marketplace_hash, post_id)
+    });
+}
+
+#[test]
+fn underpaying_bid_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (_, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(&mut context, MarketError::InvalidPaymentAmount, |context| {
+        cep82::marketplace::bid(
+            context,
+            market.marketplace_hash,
+            post_id,
+            bid_purse,
+            999_999.into(),
+        )
+    });
+}
+
+#[test]
+fn posting_without_approval_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let market = setup_market(&mut context, seller.key(), &[seller.key()]);
+
+    set_current_sender(None);
+    let (_, _, token_id) = cep78::mint(&mut context, market.cep78_hash, seller.key());
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::zero());
+
+    set_current_sender(seller.address);
+    expect_revert(&mut context, MarketError::MustBeApproved, |context| {
+        cep82::marketplace::post(
+            context,
+            market.marketplace_hash,
+            market.cep78_package,
+            token_id,
+            1_000_000.into(),
+            post_purse,
+            None,
+            None,
+        );
+    });
+}
+
+#[test]
+fn posting_from_unregistered_collection_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let market = setup_market(&mut context, seller.key(), &[seller.key()]);
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::zero());
+
+    set_current_sender(seller.address);
+    expect_revert(
+        &mut context,
+        MarketError::UnsupportedNFTContract,
+        |context| {
+            cep82::marketplace::post(
+                context,
+                market.marketplace_hash,
+                ContractPackageHash::new([7; 32]),
+                0,
+                1_000_000.into(),
+                post_purse,
+                None,
+                None,
+            );
+        },
+    );
+}
+
+#[test]
+fn bid_on_unknown_post_is_rejected() {
+    let mut context = setup_context();
+
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, buyer.key(), &[buyer.key()]);
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(&mut context, MarketError::UnknownPostId, |context| {
+        cep82::marketplace::bid(
+            context,
+            market.marketplace_hash,
+            999,
+            bid_purse,
+            1_000_000.into(),
+        )
+    });
+}
+
+#[test]
+fn quote_overflowing_total_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let market = setup_market(&mut context, seller.key(), &[seller.key()]);
+
+    let token_id = mint_approved(&mut context, &market, &seller);
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::zero());
+    let post_id = cep82::marketplace::post(
+        &mut context,
+        market.marketplace_hash,
+        market.cep78_package,
+        token_id,
+        U512::MAX,
+        post_purse,
+        None,
+        Some(RoyaltyMode::BuyerPays),
+    );
+
+    // the flat royalty on top of the price doesn't fit in a U512
+    expect_revert(&mut context, MarketError::ArithmeticOverflow, |context| {
+        cep82::marketplace::quote(context, market.marketplace_hash, post_id);
+    });
+}
+
+#[test]
+fn bid_on_listing_reserved_for_another_buyer_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let other = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market(
+        &mut context,
+        seller.key(),
+        &[seller.key(), buyer.key(), other.key()],
+    );
+
+    let (_, post_id) = mint_and_post(
+        &mut context,
+        &market,
+        &seller,
+        1_000_000,
+        Some(buyer.key()),
+        None,
+    );
+
+    set_current_sender(other.address);
+    let bid_purse = funded_purse(&mut context, &other);
+    expect_revert(
+        &mut context,
+        MarketError::ReservedForAnotherBuyer,
+        |context| {
+            cep82::marketplace::bid(
+                context,
+                market.marketplace_hash,
+                post_id,
+                bid_purse,
+                1_000_000.into(),
+            )
+        },
+    );
+}
+
+#[test]
+fn bid_on_listing_with_revoked_approval_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (token_id, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    // approving someone else replaces the marketplace's approval
+    set_current_sender(seller.address);
+    cep78::approve(&mut context, market.cep78_hash, token_id, buyer.key());
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(&mut context, MarketError::StaleListing, |context| {
+        cep82::marketplace::bid(
+            context,
+            market.marketplace_hash,
+            post_id,
+            bid_purse,
+            1_000_000.into(),
+        )
+    });
+}
+
+#[test]
+fn empty_bundle_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let market = setup_market(&mut context, seller.key(), &[seller.key()]);
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::zero());
+
+    set_current_sender(seller.address);
+    expect_revert(&mut context, MarketError::InvalidBundle, |context| {
+        cep82::bundle::post_bundle(
+            context,
+            market.marketplace_hash,
+            vec![],
+            post_purse,
+            U512::from(1_000_000),
+        );
+    });
+}
+
+#[test]
+fn swap_with_self_is_rejected() {
+    let mut context = setup_context();
+
+    let alice = UserAccount::unique_account(&mut context, 10);
+    let market = setup_market(&mut context, alice.key(), &[alice.key()]);
+
+    let alice_token = mint_approved(&mut context, &market, &alice);
+    let items = vec![BundleItem {
+        nft_contract: market.cep78_package,
+        token_id: alice_token,
+    }];
+
+    let alice_purse = funded_purse(&mut context, &alice);
+    expect_revert(&mut context, MarketError::InvalidSwap, |context| {
+        cep82::swap::propose_swap(
+            context,
+            market.marketplace_hash,
+            alice.key(),
+            items.clone(),
+            U512::from(1_000_000),
+            items,
+            U512::from(1_000_000),
+            U512::zero(),
+            U512::zero(),
+            alice_purse,
+        );
+    });
+}
+
+#[test]
+fn install_with_platform_fee_above_100_percent_is_rejected() {
+    let mut context = setup_context();
+
+    expect_revert(&mut context, MarketError::InvalidPlatformFee, |context| {
+        deploy_cep82_marketplace_with_fee(&mut context.builder, context.account.address, 10_001);
+    });
+}
+
+#[test]
+fn setting_platform_fee_above_100_percent_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let market = setup_market(&mut context, seller.key(), &[seller.key()]);
+
+    set_current_sender(None);
+    expect_revert(&mut context, MarketError::InvalidPlatformFee, |context| {
+        cep82::marketplace::set_platform_fee_bps(context, market.marketplace_hash, 10_001)
+    });
+}
+
+#[test]
+fn withdrawing_platform_fees_without_treasurer_role_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let market = setup_market(&mut context, seller.key(), &[seller.key()]);
+
+    set_current_sender(seller.address);
+    expect_revert(&mut context, CommonError::MissingRole, |context| {
+        cep82::marketplace::withdraw_platform_fees(
+            context,
+            market.marketplace_hash,
+            seller.address,
+            U512::zero(),
+        )
+    });
+}
+
+#[test]
+fn sale_through_marketplace_outside_whitelist_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market_with(
+        &mut context,
+        seller.key(),
+        &[seller.key(), buyer.key()],
+        vec![ContractPackageHash::new([1; 32])],
+        RoyaltyStep::basic(),
+    );
+
+    let (_, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(
+        &mut context,
+        CustodialError::MarketplaceNotWhitelisted,
+        |context| {
+            cep82::marketplace::bid(
+                context,
+                market.marketplace_hash,
+                post_id,
+                bid_purse,
+                1_000_000.into(),
+            )
+        },
+    );
+}
+
+#[test]
+fn royalty_paid_by_an_account_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+
+    let (custodial_hash, _) = deploy_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        vec![],
+        RoyaltyStep::basic(),
+        seller.key(),
+    );
+    let (cep78_hash, cep78_package) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(custodial_hash.into()),
+    );
+
+    cep78::register_owner(&mut context, cep78_hash, seller.key());
+    set_current_sender(None);
+    let (_, _, token_id) = cep78::mint(&mut context, cep78_hash, seller.key());
+
+    set_current_sender(buyer.address);
+    let source_purse = funded_purse(&mut context, &buyer);
+    expect_revert(
+        &mut context,
+        CustodialError::CallerMustBeContract,
+        |context| {
+            cep82::custodial::pay_royalty(
+                context,
+                custodial_hash,
+                cep78_package,
+                token_id,
+                source_purse,
+                buyer.key(),
+                seller.key(),
+                buyer.key(),
+                U512::from(1_000_000),
+            )
+        },
+    );
+}
+
+#[test]
+fn bundle_sale_without_approval_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let token_id = mint_approved(&mut context, &market, &seller);
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::zero());
+    let bundle_id = cep82::bundle::post_bundle(
+        &mut context,
+        market.marketplace_hash,
+        vec![BundleItem {
+            nft_contract: market.cep78_package,
+            token_id,
+        }],
+        post_purse,
+        U512::from(1_000_000),
+    );
+
+    // bundles aren't checked for staleness, so the custodial contract catches this
+    cep78::approve(&mut context, market.cep78_hash, token_id, buyer.key());
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(
+        &mut context,
+        CustodialError::CallerMustBeApproved,
+        |context| {
+            cep82::bundle::bid_bundle(
+                context,
+                market.marketplace_hash,
+                bundle_id,
+                bid_purse,
+                U512::from(1_000_000),
+            )
+        },
+    );
+}
+
+#[test]
+fn buying_own_listing_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let market = setup_market(&mut context, seller.key(), &[seller.key()]);
+
+    let (_, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    set_current_sender(seller.address);
+    let bid_purse = funded_purse(&mut context, &seller);
+    expect_revert(
+        &mut context,
+        CustodialError::SelfTransferForbidden,
+        |context| {
+            cep82::marketplace::bid(
+                context,
+                market.marketplace_hash,
+                post_id,
+                bid_purse,
+                1_000_000.into(),
+            )
+        },
+    );
+}
+
+#[test]
+fn bundle_sale_by_former_owner_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let token_id = mint_approved(&mut context, &market, &seller);
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::zero());
+    let bundle_id = cep82::bundle::post_bundle(
+        &mut context,
+        market.marketplace_hash,
+        vec![BundleItem {
+            nft_contract: market.cep78_package,
+            token_id,
+        }],
+        post_purse,
+        U512::from(1_000_000),
+    );
+
+    // the token is also listed on its own and sold there
+    let post_id = cep82::marketplace::post(
+        &mut context,
+        market.marketplace_hash,
+        market.cep78_package,
+        token_id,
+        1_000_000.into(),
+        post_purse,
+        None,
+        None,
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    cep82::marketplace::bid(
+        &mut context,
+        market.marketplace_hash,
+        post_id,
+        bid_purse,
+        1_000_000.into(),
+    );
+
+    // the new owner approves the marketplace, which makes the stale bundle look sellable
+    cep78::approve(
+        &mut context,
+        market.cep78_hash,
+        token_id,
+        market.marketplace_hash.into(),
+    );
+
+    expect_revert(&mut context, CustodialError::SourceMustBeOwner, |context| {
+        cep82::bundle::bid_bundle(
+            context,
+            market.marketplace_hash,
+            bundle_id,
+            bid_purse,
+            U512::from(1_000_000),
+        )
+    });
+}
+
+#[test]
+fn royalty_overflowing_u512_is_rejected() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let (custodial_hash, _) = deploy_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        vec![],
+        vec![RoyaltyStep::Percentage {
+            percent: U256::from(500),
+        }],
+        manager.key(),
+    );
+
+    expect_revert(&mut context, CustodialError::Overflow, |context| {
+        cep82::custodial::calculate_royalty(
+            context,
+            custodial_hash,
+            ContractPackageHash::new([7; 32]),
+            0,
+            U512::MAX,
+        );
+    });
+}
+
+#[test]
+fn bid_while_paused_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let (_, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    set_current_sender(None);
+    cep82::marketplace::set_paused(&mut context, market.marketplace_hash, true);
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(&mut context, CommonError::ContractPaused, |context| {
+        cep82::marketplace::bid(
+            context,
+            market.marketplace_hash,
+            post_id,
+            bid_purse,
+            1_000_000.into(),
+        )
+    });
+}
+
+#[test]
+fn bid_many_on_a_locked_token_is_rejected_when_all_or_nothing() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market(&mut context, manager.key(), &[seller.key(), buyer.key()]);
+
+    let (_, post_id) = mint_and_post(&mut context, &market, &seller, 1_000_000, None, None);
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_transfer_lock(&mut context, market.custodial_hash, 1_000);
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(&mut context, MarketError::CustodialUnavailable, |context| {
+        cep82::marketplace::bid_many(
+            context,
+            market.marketplace_hash,
+            vec![post_id],
+            bid_purse,
+            U512::from(5_000_000),
+            true,
+        );
+    });
+}
+
+#[test]
+fn bid_from_within_a_royalty_payment_is_rejected() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let (marketplace_hash, _) =
+        deploy_cep82_marketplace(&mut context.builder, context.account.address);
+    let (cep78_hash, cep78_package) =
+        deploy_cep78(&mut context.builder, context.account.address, None);
+    for owner in [seller.key(), buyer.key()] {
+        cep78::register_owner(&mut context, cep78_hash, owner);
+    }
+
+    // the collection's custodial contract bids again when it's paid the royalty
+    let custodial_package = install_reentrant_custodial(&mut context, marketplace_hash);
+    set_current_sender(None);
+    cep82::marketplace::register_nft(
+        &mut context,
+        marketplace_hash,
+        cep78_package,
+        Some(custodial_package),
+    );
+    let (_, post_id) = mint_and_post_plain(
+        &mut context,
+        marketplace_hash,
+        cep78_hash,
+        cep78_package,
+        &seller,
+        1_000_000,
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(&mut context, CommonError::ReentrantCall, |context| {
+        cep82::marketplace::bid(
+            context,
+            marketplace_hash,
+            post_id,
+            bid_purse,
+            1_000_000.into(),
+        )
+    });
+}
+
+#[test]
+fn registering_collection_without_manager_role_is_rejected() {
+    let mut context = setup_context();
+
+    let other = UserAccount::unique_account(&mut context, 10);
+    let market = setup_market(&mut context, other.key(), &[]);
+
+    set_current_sender(other.address);
+    expect_revert(&mut context, CommonError::MissingRole, |context| {
+        cep82::marketplace::register_nft(
+            context,
+            market.marketplace_hash,
+            ContractPackageHash::new([7; 32]),
+            None,
+        )
+    });
+}
+
+#[test]
+fn transfer_outside_a_marketplace_is_denied_by_the_collection() {
+    let mut context = setup_context();
+
+    let seller = UserAccount::unique_account(&mut context, 10);
+    let buyer = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, seller.key(), &[seller.key(), buyer.key()]);
+
+    let token_id = mint_approved(&mut context, &market, &seller);
+
+    // no royalty was paid, so the custodial contract tells the collection to deny the transfer
+    expect_revert(
+        &mut context,
+        Cep78Error::TransferFilterContractDenied,
+        |context| {
+            cep78::transfer(
+                context,
+                market.cep78_hash,
+                token_id,
+                seller.key(),
+                buyer.key(),
+            )
+        },
+    );
+    assert_eq!(
+        errors::describe(Cep78Error::TransferFilterContractDenied.into()),
+        Some(("Cep78Error", "TransferFilterContractDenied"))
+    );
+}
+
+#[test]
+fn second_royalty_payment_for_the_same_sale_is_rejected() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let collection =
+        setup_custodial_collection(&mut context, manager.key(), &[owner.key(), buyer.key()]);
+    let token_id = collection.token_ids[0];
+
+    let caller = install_custodial_caller(&mut context);
+    set_current_sender(owner.address);
+    cep78::approve(&mut context, collection.cep78_hash, token_id, caller.into());
+
+    set_current_sender(buyer.address);
+    let source_purse = funded_purse(&mut context, &buyer);
+    expect_revert(&mut context, CustodialError::AlreadyPaid, |context| {
+        call_contract(
+            context,
+            caller,
+            "pay_royalty_twice",
+            runtime_args! {
+                "custodial" => collection.custodial_package,
+                "token_contract" => collection.cep78_package,
+                "token_id" => token_id,
+                "source_purse" => source_purse,
+                "payer" => buyer.key(),
+                "source_key" => owner.key(),
+                "target_key" => buyer.key(),
+                "payment_amount" => U512::from(1_000_000),
+            },
+        )
+    });
+}
+
+#[test]
+fn transfer_fee_paid_by_a_contract_is_rejected() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let friend = UserAccount::unique_account(&mut context, 30);
+    let collection = setup_custodial_collection(&mut context, manager.key(), &[owner.key()]);
+
+    let caller = install_custodial_caller(&mut context);
+    set_current_sender(owner.address);
+    let source_purse = funded_purse(&mut context, &owner);
+    expect_revert(
+        &mut context,
+        CustodialError::CallerMustBeAccount,
+        |context| {
+            call_contract(
+                context,
+                caller,
+                "pay_transfer_fee",
+                runtime_args! {
+                    "custodial" => collection.custodial_package,
+                    "token_contract" => collection.cep78_package,
+                    "token_id" => collection.token_ids[0],
+                    "source_purse" => source_purse,
+                    "target_key" => friend.key(),
+                },
+            )
+        },
+    );
+}
+
+#[test]
+fn direct_transfer_of_another_accounts_token_is_rejected() {
+    let mut context = setup_context();
+
+    let owner = UserAccount::unique_account(&mut context, 10);
+    let thief = UserAccount::unique_account(&mut context, 20);
+
+    let (custodial_hash, custodial_package) = deploy_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        vec![],
+        RoyaltyStep::basic(),
+        owner.key(),
+    );
+    let (cep78_hash, cep78_package) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(custodial_hash.into()),
+    );
+
+    cep78::register_owner(&mut context, cep78_hash, owner.key());
+    set_current_sender(owner.address);
+    cep82::custodial::set_collection(&mut context, custodial_hash, cep78_package);
+    cep82::custodial::set_transfer_fee(&mut context, custodial_hash, Some(U512::zero()));
+    set_current_sender(None);
+    let (_, _, token_id) = cep78::mint(&mut context, cep78_hash, owner.key());
+
+    expect_revert(&mut context, CustodialError::SourceMustBeOwner, |context| {
+        cep82::custodial::transfer_directly(
+            context,
+            thief.address,
+            custodial_package,
+            cep78_package,
+            token_id,
+            thief.key(),
+        )
+    });
+}
+
+#[test]
+fn claim_before_the_collection_is_set_is_rejected() {
+    let mut context = setup_context();
+
+    let owner = UserAccount::unique_account(&mut context, 10);
+    let (custodial_hash, custodial_package) = deploy_cep82_custodial(
+        &mut context.builder,
+        context.account.address,
+        vec![],
+        RoyaltyStep::basic(),
+        context.account.key(),
+    );
+    let (cep78_hash, _) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(custodial_hash.into()),
+    );
+
+    set_current_sender(None);
+    expect_revert(&mut context, CustodialError::CollectionNotSet, |context| {
+        cep82::custodial::mint_and_claim(
+            context,
+            cep78_hash,
+            custodial_hash,
+            custodial_package,
+            owner.key(),
+        );
+    });
+}
+
+#[test]
+fn direct_transfer_before_a_fee_is_set_is_rejected() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let friend = UserAccount::unique_account(&mut context, 30);
+    let collection = setup_custodial_collection(&mut context, manager.key(), &[owner.key()]);
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_transfer_fee(&mut context, collection.custodial_hash, None);
+
+    expect_revert(
+        &mut context,
+        CustodialError::DirectTransfersDisabled,
+        |context| {
+            cep82::custodial::transfer_directly(
+                context,
+                owner.address,
+                collection.custodial_package,
+                collection.cep78_package,
+                collection.token_ids[0],
+                friend.key(),
+            )
+        },
+    );
+}
+
+#[test]
+fn royalty_for_a_token_of_another_collection_is_rejected() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market(&mut context, manager.key(), &[seller.key()]);
+
+    // a second collection filtered by the same custodial contract, which is pointed at the first
+    let (other_cep78_hash, other_cep78_package) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(market.custodial_hash.into()),
+    );
+    cep78::register_owner(&mut context, other_cep78_hash, seller.key());
+    set_current_sender(None);
+    cep82::marketplace::register_nft(
+        &mut context,
+        market.marketplace_hash,
+        other_cep78_package,
+        Some(market.custodial_package),
+    );
+
+    let other_market = Market {
+        cep78_hash: other_cep78_hash,
+        cep78_package: other_cep78_package,
+        ..market
+    };
+    let token_id = mint_approved(&mut context, &other_market, &seller);
+    let post_purse = new_purse(&mut context, seller.address, "post_purse", U512::zero());
+    let post_id = cep82::marketplace::post(
+        &mut context,
+        other_market.marketplace_hash,
+        other_cep78_package,
+        token_id,
+        1_000_000.into(),
+        post_purse,
+        None,
+        None,
+    );
+
+    set_current_sender(buyer.address);
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(&mut context, CustodialError::UnknownCollection, |context| {
+        cep82::marketplace::bid(
+            context,
+            other_market.marketplace_hash,
+            post_id,
+            bid_purse,
+            1_000_000.into(),
+        )
+    });
+}
+
+#[test]
+fn can_transfer_called_by_another_collection_is_rejected() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let owner = UserAccount::unique_account(&mut context, 20);
+    let friend = UserAccount::unique_account(&mut context, 30);
+    let collection =
+        setup_custodial_collection(&mut context, manager.key(), &[owner.key(), friend.key()]);
+
+    // a second collection filtered by the same custodial contract, which is pointed at the first
+    let (other_cep78_hash, _) = deploy_cep78(
+        &mut context.builder,
+        context.account.address,
+        Some(collection.custodial_hash.into()),
+    );
+    cep78::register_owner(&mut context, other_cep78_hash, owner.key());
+    cep78::register_owner(&mut context, other_cep78_hash, friend.key());
+    set_current_sender(None);
+    let (_, _, other_token_id) = cep78::mint(&mut context, other_cep78_hash, owner.key());
+
+    set_current_sender(manager.address);
+    cep82::custodial::exempt_from_royalty(
+        &mut context,
+        collection.custodial_hash,
+        owner.key(),
+        u64::MAX,
+    );
+
+    set_current_sender(owner.address);
+    expect_revert(&mut context, CustodialError::UnknownCollection, |context| {
+        cep78::transfer(
+            context,
+            other_cep78_hash,
+            other_token_id,
+            owner.key(),
+            friend.key(),
+        )
+    });
+
+    // the exemption is still there for a token of the collection
+    cep78::transfer(
+        &mut context,
+        collection.cep78_hash,
+        collection.token_ids[0],
+        owner.key(),
+        friend.key(),
+    );
+    assert_eq!(
+        cep78::owner_of(&mut context, collection.cep78_hash, collection.token_ids[0]),
+        friend.key()
+    );
+}
+
+#[test]
+fn delegate_set_by_someone_other_than_the_owner_is_rejected() {
+    let mut context = setup_context();
+
+    let owner = UserAccount::unique_account(&mut context, 10);
+    let stranger = UserAccount::unique_account(&mut context, 20);
+    let collection = setup_custody(&mut context, &[owner.key()]);
+
+    let (_, _, token_id) = cep82::custodial::mint_and_claim(
+        &mut context,
+        collection.cep78_hash,
+        collection.custodial_hash,
+        collection.custodial_package,
+        owner.key(),
+    );
+
+    set_current_sender(stranger.address);
+    expect_revert(&mut context, CustodialError::CallerMustBeOwner, |context| {
+        cep82::custodial::set_delegate(
+            context,
+            collection.custodial_hash,
+            token_id,
+            Some(collection.custodial_package),
+        )
+    });
+}
+
+#[test]
+fn royalty_reported_to_a_settling_custodial_is_rejected() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market(&mut context, manager.key(), &[seller.key(), buyer.key()]);
+
+    set_current_sender(manager.address);
+    cep82::custodial::set_settlement_required(&mut context, market.custodial_hash, true);
+
+    let token_id = mint_approved(&mut context, &market, &seller);
+
+    set_current_sender(buyer.address);
+    let source_purse = funded_purse(&mut context, &buyer);
+    expect_revert(
+        &mut context,
+        CustodialError::SettlementRequired,
+        |context| {
+            cep82::custodial::pay_royalty(
+                context,
+                market.custodial_hash,
+                market.cep78_package,
+                token_id,
+                source_purse,
+                buyer.key(),
+                seller.key(),
+                buyer.key(),
+                U512::from(1_000_000),
+            )
+        },
+    );
+}
+
+#[test]
+fn buyer_paid_royalty_cannot_be_settled_by_the_custodial() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let buyer = UserAccount::unique_account(&mut context, 30);
+    let market = setup_market(&mut context, manager.key(), &[seller.key(), buyer.key()]);
+
+    let (_, post_id) = mint_and_post(
+        &mut context,
+        &market,
+        &seller,
+        1_000_000,
+        None,
+        Some(RoyaltyMode::BuyerPays),
+    );
+
+    // the listing was posted before the custodial contract required settlement
+    require_settlement(&mut context, &market, &manager);
+
+    set_current_sender(buyer.address);
+    expect_revert(
+        &mut context,
+        MarketError::UnsupportedRoyaltyMode,
+        |context| {
+            cep82::marketplace::quote(context, market.marketplace_hash, post_id);
+        },
+    );
+
+    let bid_purse = funded_purse(&mut context, &buyer);
+    expect_revert(
+        &mut context,
+        MarketError::UnsupportedRoyaltyMode,
+        |context| {
+            cep82::marketplace::bid(
+                context,
+                market.marketplace_hash,
+                post_id,
+                bid_purse,
+                1_000_100.into(),
+            )
+        },
+    );
+}
+
+#[test]
+fn buyer_paid_royalty_cannot_be_posted_for_a_settling_custodial() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let seller = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, manager.key(), &[seller.key()]);
+
+    require_settlement(&mut context, &market, &manager);
+
+    expect_revert(
+        &mut context,
+        MarketError::UnsupportedRoyaltyMode,
+        |context| {
+            mint_and_post(
+                context,
+                &market,
+                &seller,
+                1_000_000,
+                None,
+                Some(RoyaltyMode::BuyerPays),
+            );
+        },
+    );
+}
+
+#[test]
+fn rebate_over_the_whole_royalty_is_rejected() {
+    let mut context = setup_context();
+
+    let manager = UserAccount::unique_account(&mut context, 10);
+    let partner = UserAccount::unique_account(&mut context, 20);
+    let market = setup_market(&mut context, manager.key(), &[]);
+
+    let config = MarketplaceConfig {
+        rebate_bps: 10_001,
+        rebate_account: Some(partner.address),
+        ..MarketplaceConfig::default()
+    };
+    set_current_sender(manager.address);
+    expect_revert(&mut context, CustodialError::InvalidRebate, |context| {
+        cep82::custodial::set_marketplace_config(
+            context,
+            market.custodial_hash,
+            market.marketplace_package,
+            Some(config),
+        )
+    });
+}
@@ -1,5 +1,7 @@
 use std::{
+    fmt::Debug,
     iter::repeat,
+    panic::{self, AssertUnwindSafe},
     sync::{
         atomic::{AtomicUsize, Ordering},
         Mutex,
@@ -27,8 +29,8 @@ use casper_execution_engine::{
 use casper_types::{
     account::AccountHash,
     bytesrepr::{Bytes, FromBytes, ToBytes},
-    runtime_args, ApiError, CLValue, ContractHash, Key, Motes, PublicKey, RuntimeArgs, SecretKey,
-    StoredValue, URef, U256, U512,
+    runtime_args, ApiError, CLValue, ContractHash, ContractPackageHash, Key, Motes, PublicKey,
+    RuntimeArgs, SecretKey, StoredValue, URef, U256, U512,
 };
 use once_cell::sync::Lazy;
 
@@ -36,6 +38,7 @@ pub mod cep78;
 pub mod cep82;
 pub mod deploy;
 pub mod erc20;
+pub mod errors;
 pub mod state;
 
 const TEST_ACCOUNT_BALANCE: u64 = 10_000_000_000_000u64;
@@ -44,7 +47,13 @@ const CONTRACT_ERC20_BYTES: &[u8] = include_bytes!("../../wasm/erc20.wasm");
 const CONTRACT_CEP78_BYTES: &[u8] = include_bytes!("../../wasm/cep78.wasm");
 const CONTRACT_CEP82_MARKETPLACE_BYTES: &[u8] = include_bytes!("../../wasm/cep82-marketplace.wasm");
 const CONTRACT_CEP82_CUSTODIAL_BYTES: &[u8] = include_bytes!("../../wasm/cep82-custodial.wasm");
+/// The contracts as first released, for testing upgrades.
+const CONTRACT_CEP82_MARKETPLACE_V0_BYTES: &[u8] =
+    include_bytes!("../../wasm/cep82-marketplace-v0.wasm");
+const CONTRACT_CEP82_CUSTODIAL_V0_BYTES: &[u8] =
+    include_bytes!("../../wasm/cep82-custodial-v0.wasm");
 const CONTRACT_TESTUTIL_BYTES: &[u8] = include_bytes!("../../wasm/testutil.wasm");
+const CONTRACT_CEP82_TRANSFER_BYTES: &[u8] = include_bytes!("../../wasm/cep82-transfer.wasm");
 static DEPLOY_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 static CURRENT_SENDER: Lazy<Mutex<Option<AccountHash>>> = Lazy::new(|| Mutex::new(None));
@@ -65,6 +74,8 @@ pub fn deploy_builder() -> DeployItemBuilder {
 pub struct TestContext {
     pub account: UserAccount,
     pub builder: InMemoryWasmTestBuilder,
+    /// Block time in milliseconds that contract calls are executed at.
+    pub block_time: u64,
 }
 
 pub struct UserAccount {
@@ -140,6 +151,7 @@ pub fn setup_context() -> TestContext {
     TestContext {
         account: account_data,
         builder,
+        block_time: 0,
     }
 }
 
@@ -218,6 +230,40 @@ pub fn execution_error(context: &mut TestContext, deploy_item: DeployItem) -> En
         .unwrap()
 }
 
+/// Return the `ApiError::User` code the last deploy reverted with, if any.
+pub fn last_user_error(context: &mut TestContext) -> Option<u16> {
+    let exec_results = context.builder.get_exec_results().last()?;
+    match exec_results.get(0)?.as_error()? {
+        engine_state::Error::Exec(execution::Error::Revert(ApiError::User(code))) => Some(*code),
+        _ => None,
+    }
+}
+
+/// Run `f`, expecting the deploy it executes to revert with `error`.
+///
+/// The call helpers panic when a deploy fails, so the panic is caught and the result of the last
+/// deploy is checked instead. `error` is either one of our error enums, or a `Cep78Error` for
+/// reverts that happen inside the collection.
+pub fn expect_revert<E, F>(context: &mut TestContext, error: E, f: F)
+where
+    E: Into<u16> + Copy + Debug,
+    F: FnOnce(&mut TestContext),
+{
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(context)));
+    assert!(
+        result.is_err(),
+        "Expected a revert with {error:?}, but the call succeeded"
+    );
+
+    let code = last_user_error(context);
+    assert_eq!(
+        code,
+        Some(error.into()),
+        "Expected a revert with {error:?}, got {:?}",
+        code.map(|code| errors::describe(code).unwrap_or(("unknown error", "")))
+    );
+}
+
 pub fn set_current_sender<T: Into<Option<AccountHash>>>(account: T) {
     let mut current_sender = CURRENT_SENDER.lock().unwrap();
     *current_sender = account.into();
@@ -249,6 +295,7 @@ pub fn call_contract_with_result<T: FromBytes>(
 
     let call_request =
         ExecuteRequestBuilder::module_bytes(sender, CONTRACT_TESTUTIL_BYTES.to_vec(), runtime_args)
+            .with_block_time(context.block_time)
             .build();
 
     context
@@ -284,6 +331,7 @@ pub fn call_contract(
         entry_point,
         args,
     )
+    .with_block_time(context.block_time)
     .build();
 
     context
@@ -309,6 +357,7 @@ pub fn new_purse(
         CONTRACT_TESTUTIL_BYTES.to_vec(),
         runtime_args,
     )
+    .with_block_time(context.block_time)
     .build();
 
     context
@@ -325,6 +374,85 @@ pub fn new_purse(
     }
 }
 
+/// Install a contract that poses as a custodial contract and, when `marketplace` pays it a
+/// royalty, bids on the marketplace again from within that call. Returns its package hash.
+pub fn install_reentrant_custodial(
+    context: &mut TestContext,
+    marketplace: ContractHash,
+) -> ContractPackageHash {
+    let account = context.account.address;
+    let call_request = ExecuteRequestBuilder::module_bytes(
+        account,
+        CONTRACT_TESTUTIL_BYTES.to_vec(),
+        runtime_args! {
+            "action" => "install_reentrant_custodial",
+            "marketplace" => marketplace,
+        },
+    )
+    .with_block_time(context.block_time)
+    .build();
+
+    context
+        .builder
+        .exec(call_request)
+        .commit()
+        .expect_success_ex();
+
+    let package =
+        context.builder.get_account(account).unwrap().named_keys()["reentrant_custodial_package"];
+    ContractPackageHash::new(package.into_hash().unwrap())
+}
+
+/// Install a contract that calls a custodial contract's payment entry points the way no
+/// marketplace of ours does. Returns its contract hash.
+pub fn install_custodial_caller(context: &mut TestContext) -> ContractHash {
+    let account = context.account.address;
+    let call_request = ExecuteRequestBuilder::module_bytes(
+        account,
+        CONTRACT_TESTUTIL_BYTES.to_vec(),
+        runtime_args! {
+            "action" => "install_custodial_caller",
+        },
+    )
+    .with_block_time(context.block_time)
+    .build();
+
+    context
+        .builder
+        .exec(call_request)
+        .commit()
+        .expect_success_ex();
+
+    let contract =
+        context.builder.get_account(account).unwrap().named_keys()["custodial_caller_contract"];
+    ContractHash::new(contract.into_hash().unwrap())
+}
+
+/// Install a contract that calls CEP-78 collections through `contract_common::ext::cep78`.
+/// Returns its contract hash.
+pub fn install_cep78_client(context: &mut TestContext) -> ContractHash {
+    let account = context.account.address;
+    let call_request = ExecuteRequestBuilder::module_bytes(
+        account,
+        CONTRACT_TESTUTIL_BYTES.to_vec(),
+        runtime_args! {
+            "action" => "install_cep78_client",
+        },
+    )
+    .with_block_time(context.block_time)
+    .build();
+
+    context
+        .builder
+        .exec(call_request)
+        .commit()
+        .expect_success_ex();
+
+    let contract =
+        context.builder.get_account(account).unwrap().named_keys()["cep78_client_contract"];
+    ContractHash::new(contract.into_hash().unwrap())
+}
+
 trait TestBuilderExt {
     fn expect_success_ex(&mut self) -> &mut Self;
 }
@@ -348,6 +476,10 @@ impl TestBuilderExt for WasmTestBuilder<InMemoryGlobalState> {
                 if error != erc20::Error::Unknown {
                     eprintln!("Possible ERC20 error: {error:?}");
                 }
+
+                if let Some((enum_name, variant)) = errors::describe(*code) {
+                    eprintln!("Contract error: {enum_name}::{variant}");
+                }
             }
 
             panic!(
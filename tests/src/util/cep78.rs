@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 
 use casper_types::{
     account::AccountHash, bytesrepr::Bytes, runtime_args, CLValue, ContractHash, Key, RuntimeArgs,
+    URefAddr,
 };
 
 pub mod consts {
@@ -143,6 +144,7 @@ use consts::{
 
 use self::consts::{
     ARG_ACL_WHITELIST, ARG_EVENTS_MODE, ARG_TRANSFER_FILTER_CONTRACT, TEST_PRETTY_721_META_DATA,
+    TOKEN_ISSUERS, TRANSFER_FILTER_CONTRACT,
 };
 
 use super::{call_contract_with_result, TestContext};
@@ -588,3 +590,286 @@ pub fn approve(context: &mut TestContext, contract: ContractHash, token_id: u64,
         },
     )
 }
+
+pub fn set_approval_for_all(
+    context: &mut TestContext,
+    contract: ContractHash,
+    approve_all: bool,
+    operator: Key,
+) {
+    call_contract_with_result(
+        context,
+        contract,
+        "set_approval_for_all",
+        runtime_args! {
+            "approve_all" => approve_all,
+            "operator" => operator,
+        },
+    )
+}
+
+pub fn transfer(
+    context: &mut TestContext,
+    contract: ContractHash,
+    token_id: u64,
+    source_key: Key,
+    target_key: Key,
+) {
+    call_contract_with_result::<(String, Key)>(
+        context,
+        contract,
+        "transfer",
+        runtime_args! {
+            "token_id" => token_id,
+            "source_key" => source_key,
+            "target_key" => target_key,
+        },
+    );
+}
+
+/// Read the account that minted `token_id` from the collection's state. CEP-78 doesn't expose the
+/// issuer through an entry point.
+pub fn token_issuer(context: &mut TestContext, contract: ContractHash, token_id: u64) -> Key {
+    let token_issuers = named_key(context, contract, TOKEN_ISSUERS)
+        .and_then(|key| key.into_uref())
+        .unwrap();
+
+    context
+        .builder
+        .query_dictionary_item(None, token_issuers, &token_id.to_string())
+        .unwrap()
+        .as_cl_value()
+        .cloned()
+        .unwrap()
+        .into_t::<Key>()
+        .unwrap()
+}
+
+/// Read the transfer filter contract the collection was installed with, if any.
+pub fn transfer_filter_contract(context: &mut TestContext, contract: ContractHash) -> Option<Key> {
+    let filter_key = named_key(context, contract, TRANSFER_FILTER_CONTRACT)?;
+
+    context
+        .builder
+        .query(None, filter_key, &[])
+        .unwrap()
+        .as_cl_value()
+        .cloned()
+        .unwrap()
+        .into_t::<Key>()
+        .ok()
+}
+
+/// Address of the collection's `token_issuers` dictionary, which contracts need to read issuers.
+pub fn token_issuers_address(context: &mut TestContext, contract: ContractHash) -> URefAddr {
+    named_key(context, contract, TOKEN_ISSUERS)
+        .and_then(|key| key.into_uref())
+        .unwrap()
+        .addr()
+}
+
+fn named_key(context: &mut TestContext, contract: ContractHash, name: &str) -> Option<Key> {
+    context
+        .builder
+        .query(None, Key::Hash(contract.value()), &[])
+        .unwrap()
+        .as_contract()
+        .unwrap()
+        .named_keys()
+        .get(name)
+        .copied()
+}
+
+/// Calls made by the CEP-78 client contract of the test utilities, which goes through
+/// `contract_common::ext::cep78` on its own behalf.
+pub mod client {
+    use casper_types::{
+        runtime_args, ContractHash, ContractPackageHash, Key, RuntimeArgs, URefAddr,
+    };
+
+    use crate::util::{call_contract, call_contract_with_result, TestContext};
+
+    use super::consts::TEST_PRETTY_721_META_DATA;
+
+    /// Mint a token of `collection` to the client. Returns its id.
+    pub fn mint(
+        context: &mut TestContext,
+        client: ContractHash,
+        collection: ContractPackageHash,
+    ) -> u64 {
+        call_contract_with_result::<String>(
+            context,
+            client,
+            "cep78_mint",
+            runtime_args! {
+                "collection" => collection,
+                "token_meta_data" => TEST_PRETTY_721_META_DATA.to_string(),
+            },
+        )
+        .parse()
+        .unwrap()
+    }
+
+    pub fn metadata(
+        context: &mut TestContext,
+        client: ContractHash,
+        collection: ContractPackageHash,
+        token_id: u64,
+    ) -> String {
+        call_contract_with_result(
+            context,
+            client,
+            "cep78_metadata",
+            runtime_args! {
+                "collection" => collection,
+                "token_id" => token_id,
+            },
+        )
+    }
+
+    pub fn token_issuer(
+        context: &mut TestContext,
+        client: ContractHash,
+        token_issuers: URefAddr,
+        token_id: u64,
+    ) -> Option<Key> {
+        call_contract_with_result(
+            context,
+            client,
+            "cep78_token_issuer",
+            runtime_args! {
+                "token_issuers" => token_issuers,
+                "token_id" => token_id,
+            },
+        )
+    }
+
+    pub fn balance_of(
+        context: &mut TestContext,
+        client: ContractHash,
+        collection: ContractPackageHash,
+        token_owner: Key,
+    ) -> u64 {
+        call_contract_with_result(
+            context,
+            client,
+            "cep78_balance_of",
+            runtime_args! {
+                "collection" => collection,
+                "token_owner" => token_owner,
+            },
+        )
+    }
+
+    pub fn approve(
+        context: &mut TestContext,
+        client: ContractHash,
+        collection: ContractPackageHash,
+        token_id: u64,
+        spender: Key,
+    ) {
+        call_contract(
+            context,
+            client,
+            "cep78_approve",
+            runtime_args! {
+                "collection" => collection,
+                "token_id" => token_id,
+                "spender" => spender,
+            },
+        )
+    }
+
+    pub fn revoke(
+        context: &mut TestContext,
+        client: ContractHash,
+        collection: ContractPackageHash,
+        token_id: u64,
+    ) {
+        call_contract(
+            context,
+            client,
+            "cep78_revoke",
+            runtime_args! {
+                "collection" => collection,
+                "token_id" => token_id,
+            },
+        )
+    }
+
+    pub fn set_approval_for_all(
+        context: &mut TestContext,
+        client: ContractHash,
+        collection: ContractPackageHash,
+        approve_all: bool,
+        operator: Key,
+    ) {
+        call_contract(
+            context,
+            client,
+            "cep78_set_approval_for_all",
+            runtime_args! {
+                "collection" => collection,
+                "approve_all" => approve_all,
+                "operator" => operator,
+            },
+        )
+    }
+
+    pub fn is_approved_for_all(
+        context: &mut TestContext,
+        client: ContractHash,
+        collection: ContractPackageHash,
+        token_owner: Key,
+        operator: Key,
+    ) -> bool {
+        call_contract_with_result(
+            context,
+            client,
+            "cep78_is_approved_for_all",
+            runtime_args! {
+                "collection" => collection,
+                "token_owner" => token_owner,
+                "operator" => operator,
+            },
+        )
+    }
+
+    pub fn is_approved(
+        context: &mut TestContext,
+        client: ContractHash,
+        collection: ContractPackageHash,
+        token_id: u64,
+        owner: Key,
+        spender: Key,
+    ) -> bool {
+        call_contract_with_result(
+            context,
+            client,
+            "cep78_is_approved",
+            runtime_args! {
+                "collection" => collection,
+                "token_id" => token_id,
+                "owner" => owner,
+                "spender" => spender,
+            },
+        )
+    }
+
+    pub fn burn(
+        context: &mut TestContext,
+        client: ContractHash,
+        collection: ContractPackageHash,
+        token_id: u64,
+    ) {
+        call_contract(
+            context,
+            client,
+            "cep78_burn",
+            runtime_args! {
+                "collection" => collection,
+                "token_id" => token_id,
+            },
+        )
+    }
+}
@@ -1,11 +1,28 @@
-use casper_types::{runtime_args, ContractHash, ContractPackageHash, RuntimeArgs, U256};
+use casper_types::{runtime_args, ContractHash, ContractPackageHash, Key, RuntimeArgs, URef, U512};
 
 use super::{call_contract, TestContext};
 
+/// Read the URef stored under `name` in the named keys of `contract`.
+fn named_uref(context: &mut TestContext, contract: ContractHash, name: &str) -> URef {
+    context
+        .builder
+        .query(None, Key::Hash(contract.value()), &[])
+        .unwrap()
+        .as_contract()
+        .unwrap()
+        .named_keys()
+        .get(name)
+        .and_then(|key| key.into_uref())
+        .unwrap()
+}
+
 pub mod marketplace {
-    use casper_types::URef;
+    use casper_types::account::AccountHash;
 
-    use crate::util::call_contract_with_result;
+    use crate::util::{
+        call_contract_with_result,
+        state::{Role, RoyaltyMode, SaleQuote},
+    };
 
     use super::*;
 
@@ -24,23 +41,204 @@ pub mod marketplace {
         call_contract(context, contract, "register_cep78_contract", args)
     }
 
+    /// Register a collection whose custodial contract is of the first release.
+    pub fn register_legacy_nft(
+        context: &mut TestContext,
+        contract: ContractHash,
+        nft_package: ContractPackageHash,
+        custodial_package: ContractPackageHash,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "register_cep78_contract",
+            runtime_args! {
+                "nft_package" => nft_package,
+                "custodial_package" => custodial_package,
+                "legacy_custodial" => true,
+            },
+        )
+    }
+
     pub fn post(
         context: &mut TestContext,
         contract: ContractHash,
         nft_contract: ContractPackageHash,
         token_id: u64,
-        price: U256,
+        price: U512,
         target_purse: URef,
+        reserved_buyer: Option<Key>,
+        royalty_mode: Option<RoyaltyMode>,
     ) -> u64 {
-        call_contract_with_result::<u64>(
+        let mut args = runtime_args! {
+            "nft_contract" => nft_contract,
+            "token_id" => token_id,
+            "price" => price,
+            "target_purse" => target_purse,
+        };
+        if let Some(reserved_buyer) = reserved_buyer {
+            args.insert("reserved_buyer", reserved_buyer).unwrap();
+        }
+        if let Some(royalty_mode) = royalty_mode {
+            args.insert("royalty_mode", royalty_mode as u8).unwrap();
+        }
+
+        call_contract_with_result::<u64>(context, contract, "post", args)
+    }
+
+    pub fn cancel(context: &mut TestContext, contract: ContractHash, post_id: u64) {
+        call_contract(
             context,
             contract,
-            "post",
+            "cancel",
             runtime_args! {
-                "nft_contract" => nft_contract,
-                "token_id" => token_id,
-                "price" => price,
-                "target_purse" => target_purse,
+                "post_id" => post_id,
+            },
+        )
+    }
+
+    pub fn set_platform_fee_bps(
+        context: &mut TestContext,
+        contract: ContractHash,
+        platform_fee_bps: u64,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "set_platform_fee_bps",
+            runtime_args! {
+                "platform_fee_bps" => platform_fee_bps,
+            },
+        )
+    }
+
+    pub fn withdraw_platform_fees(
+        context: &mut TestContext,
+        contract: ContractHash,
+        target: AccountHash,
+        amount: U512,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "withdraw_platform_fees",
+            runtime_args! {
+                "target" => target,
+                "amount" => amount,
+            },
+        )
+    }
+
+    /// Balance of the purse the marketplace collects platform fees in.
+    pub fn platform_fee_purse_balance(context: &mut TestContext, contract: ContractHash) -> U512 {
+        let platform_fee_purse = named_uref(context, contract, "platform_fee_purse");
+        context.builder.get_purse_balance(platform_fee_purse)
+    }
+
+    pub fn set_paused(context: &mut TestContext, contract: ContractHash, paused: bool) {
+        call_contract(
+            context,
+            contract,
+            "set_paused",
+            runtime_args! {
+                "paused" => paused,
+            },
+        )
+    }
+
+    pub fn is_paused(context: &mut TestContext, contract: ContractHash) -> bool {
+        call_contract_with_result::<bool>(context, contract, "is_paused", runtime_args! {})
+    }
+
+    pub fn grant_role(context: &mut TestContext, contract: ContractHash, role: Role, account: Key) {
+        call_contract(
+            context,
+            contract,
+            "grant_role",
+            runtime_args! {
+                "role" => role as u8,
+                "account" => account,
+            },
+        )
+    }
+
+    pub fn revoke_role(
+        context: &mut TestContext,
+        contract: ContractHash,
+        role: Role,
+        account: Key,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "revoke_role",
+            runtime_args! {
+                "role" => role as u8,
+                "account" => account,
+            },
+        )
+    }
+
+    pub fn has_role(
+        context: &mut TestContext,
+        contract: ContractHash,
+        role: Role,
+        account: Key,
+    ) -> bool {
+        call_contract_with_result::<bool>(
+            context,
+            contract,
+            "has_role",
+            runtime_args! {
+                "role" => role as u8,
+                "account" => account,
+            },
+        )
+    }
+
+    pub fn migrate_listings(
+        context: &mut TestContext,
+        contract: ContractHash,
+        first_post_id: u64,
+        count: u64,
+    ) -> u32 {
+        call_contract_with_result::<u32>(
+            context,
+            contract,
+            "migrate_listings",
+            runtime_args! {
+                "first_post_id" => first_post_id,
+                "count" => count,
+            },
+        )
+    }
+
+    pub fn quote(context: &mut TestContext, contract: ContractHash, post_id: u64) -> SaleQuote {
+        call_contract_with_result::<SaleQuote>(
+            context,
+            contract,
+            "quote",
+            runtime_args! {
+                "post_id" => post_id,
+            },
+        )
+    }
+
+    pub fn listings_by_seller(
+        context: &mut TestContext,
+        contract: ContractHash,
+        seller: Key,
+        offset: u64,
+        limit: u64,
+    ) -> Vec<u64> {
+        call_contract_with_result::<Vec<u64>>(
+            context,
+            contract,
+            "listings_by_seller",
+            runtime_args! {
+                "seller" => seller,
+                "offset" => offset,
+                "limit" => limit,
             },
         )
     }
@@ -50,7 +248,7 @@ pub mod marketplace {
         contract: ContractHash,
         post_id: u64,
         source_purse: URef,
-        amount: U256,
+        amount: U512,
     ) {
         call_contract(
             context,
@@ -63,22 +261,678 @@ pub mod marketplace {
             },
         )
     }
+
+    pub fn bid_many(
+        context: &mut TestContext,
+        contract: ContractHash,
+        post_ids: Vec<u64>,
+        source_purse: URef,
+        max_total: U512,
+        all_or_nothing: bool,
+    ) -> (u64, U512) {
+        call_contract_with_result::<(u64, U512)>(
+            context,
+            contract,
+            "bid_many",
+            runtime_args! {
+                "post_ids" => post_ids,
+                "source_purse" => source_purse,
+                "max_total" => max_total,
+                "all_or_nothing" => all_or_nothing,
+            },
+        )
+    }
+}
+
+pub mod bundle {
+    use casper_types::URef;
+
+    use crate::util::{call_contract_with_result, state::BundleItem};
+
+    use super::*;
+
+    pub fn post_bundle(
+        context: &mut TestContext,
+        contract: ContractHash,
+        items: Vec<BundleItem>,
+        target_purse: URef,
+        price: U512,
+    ) -> u64 {
+        call_contract_with_result::<u64>(
+            context,
+            contract,
+            "post_bundle",
+            runtime_args! {
+                "items" => items,
+                "target_purse" => target_purse,
+                "price" => price,
+            },
+        )
+    }
+
+    pub fn bid_bundle(
+        context: &mut TestContext,
+        contract: ContractHash,
+        bundle_id: u64,
+        source_purse: URef,
+        amount: U512,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "bid_bundle",
+            runtime_args! {
+                "bundle_id" => bundle_id,
+                "source_purse" => source_purse,
+                "amount" => amount,
+            },
+        )
+    }
+}
+
+pub mod swap {
+    use casper_types::{Key, URef};
+
+    use crate::util::{call_contract_with_result, state::BundleItem};
+
+    use super::*;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn propose_swap(
+        context: &mut TestContext,
+        contract: ContractHash,
+        counterparty: Key,
+        proposer_items: Vec<BundleItem>,
+        proposer_value: U512,
+        counterparty_items: Vec<BundleItem>,
+        counterparty_value: U512,
+        proposer_top_up: U512,
+        counterparty_top_up: U512,
+        proposer_purse: URef,
+    ) -> u64 {
+        call_contract_with_result::<u64>(
+            context,
+            contract,
+            "propose_swap",
+            runtime_args! {
+                "counterparty" => counterparty,
+                "proposer_items" => proposer_items,
+                "proposer_value" => proposer_value,
+                "counterparty_items" => counterparty_items,
+                "counterparty_value" => counterparty_value,
+                "proposer_top_up" => proposer_top_up,
+                "counterparty_top_up" => counterparty_top_up,
+                "proposer_purse" => proposer_purse,
+            },
+        )
+    }
+
+    pub fn accept_swap(
+        context: &mut TestContext,
+        contract: ContractHash,
+        swap_id: u64,
+        source_purse: URef,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "accept_swap",
+            runtime_args! {
+                "swap_id" => swap_id,
+                "source_purse" => source_purse,
+            },
+        )
+    }
 }
 
 pub mod custodial {
-    use casper_types::{runtime_args, ContractHash, Key};
-    use casper_types::{ContractPackageHash, RuntimeArgs};
+    use casper_engine_test_support::ExecuteRequestBuilder;
+    use casper_types::{account::AccountHash, bytesrepr, runtime_args, ContractHash, Key};
+    use casper_types::{ContractPackageHash, RuntimeArgs, URef, U512};
+    use contract_common::events::EventRecord;
 
-    use crate::util::{call_contract, call_contract_with_result, cep78, TestContext};
+    use crate::util::{
+        call_contract, call_contract_with_result, cep78,
+        state::{MarketplaceConfig, MarketplaceMode, RecipientList, Role},
+        TestBuilderExt, TestContext, CONTRACT_CEP82_TRANSFER_BYTES,
+    };
 
-    pub fn claim(context: &mut TestContext, contract: ContractHash, token_id: u64, owner: Key) {
+    use super::named_uref;
+
+    pub fn calculate_royalty(
+        context: &mut TestContext,
+        contract: ContractHash,
+        token_contract: ContractPackageHash,
+        token_id: u64,
+        payment_amount: U512,
+    ) -> U512 {
+        call_contract_with_result::<U512>(
+            context,
+            contract,
+            "calculate_royalty",
+            runtime_args! {
+                "token_contract" => token_contract,
+                "token_id" => token_id,
+                "payment_amount" => payment_amount,
+            },
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn pay_royalty(
+        context: &mut TestContext,
+        contract: ContractHash,
+        token_contract: ContractPackageHash,
+        token_id: u64,
+        source_purse: URef,
+        payer: Key,
+        source_key: Key,
+        target_key: Key,
+        payment_amount: U512,
+    ) {
         call_contract(
             context,
             contract,
-            "claim",
+            "pay_royalty",
+            runtime_args! {
+                "token_contract" => token_contract,
+                "token_id" => token_id,
+                "source_purse" => source_purse,
+                "payer" => payer,
+                "source_key" => source_key,
+                "target_key" => target_key,
+                "payment_amount" => payment_amount,
+            },
+        )
+    }
+
+    pub fn has_role(
+        context: &mut TestContext,
+        contract: ContractHash,
+        role: Role,
+        account: Key,
+    ) -> bool {
+        call_contract_with_result::<bool>(
+            context,
+            contract,
+            "has_role",
+            runtime_args! {
+                "role" => role as u8,
+                "account" => account,
+            },
+        )
+    }
+
+    pub fn floor_value(context: &mut TestContext, contract: ContractHash, token_id: u64) -> U512 {
+        call_contract_with_result::<U512>(
+            context,
+            contract,
+            "floor_value",
+            runtime_args! {
+                "token_id" => token_id,
+            },
+        )
+    }
+
+    pub fn set_floor_value(context: &mut TestContext, contract: ContractHash, value: U512) {
+        call_contract(
+            context,
+            contract,
+            "set_floor_value",
+            runtime_args! {
+                "value" => value,
+            },
+        )
+    }
+
+    pub fn set_token_floor_value(
+        context: &mut TestContext,
+        contract: ContractHash,
+        token_id: u64,
+        value: Option<U512>,
+    ) {
+        let mut args = RuntimeArgs::new();
+        args.insert("token_id", token_id).unwrap();
+        if let Some(value) = value {
+            args.insert("value", value).unwrap();
+        }
+
+        call_contract(context, contract, "set_token_floor_value", args)
+    }
+
+    pub fn set_floor_follows_last_sale(
+        context: &mut TestContext,
+        contract: ContractHash,
+        enabled: bool,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "set_floor_follows_last_sale",
+            runtime_args! {
+                "enabled" => enabled,
+            },
+        )
+    }
+
+    pub fn set_marketplace_mode(
+        context: &mut TestContext,
+        contract: ContractHash,
+        mode: MarketplaceMode,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "set_marketplace_mode",
+            runtime_args! {
+                "mode" => mode as u8,
+            },
+        )
+    }
+
+    pub fn marketplace_mode(context: &mut TestContext, contract: ContractHash) -> MarketplaceMode {
+        let mode = call_contract_with_result::<u8>(
+            context,
+            contract,
+            "marketplace_mode",
+            runtime_args! {},
+        );
+        MarketplaceMode::from_u8(mode).unwrap()
+    }
+
+    pub fn add_denied_marketplaces(
+        context: &mut TestContext,
+        contract: ContractHash,
+        marketplaces: Vec<ContractPackageHash>,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "add_denied_marketplaces",
+            runtime_args! {
+                "marketplaces" => marketplaces,
+            },
+        )
+    }
+
+    pub fn remove_denied_marketplaces(
+        context: &mut TestContext,
+        contract: ContractHash,
+        marketplaces: Vec<ContractPackageHash>,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "remove_denied_marketplaces",
+            runtime_args! {
+                "marketplaces" => marketplaces,
+            },
+        )
+    }
+
+    pub fn is_marketplace_denied(
+        context: &mut TestContext,
+        contract: ContractHash,
+        marketplace: ContractPackageHash,
+    ) -> bool {
+        call_contract_with_result::<bool>(
+            context,
+            contract,
+            "is_marketplace_denied",
+            runtime_args! {
+                "marketplace" => marketplace,
+            },
+        )
+    }
+
+    pub fn marketplace_config(
+        context: &mut TestContext,
+        contract: ContractHash,
+        marketplace: ContractPackageHash,
+    ) -> Option<MarketplaceConfig> {
+        call_contract_with_result::<Option<MarketplaceConfig>>(
+            context,
+            contract,
+            "marketplace_config",
+            runtime_args! {
+                "marketplace" => marketplace,
+            },
+        )
+    }
+
+    pub fn set_marketplace_config(
+        context: &mut TestContext,
+        contract: ContractHash,
+        marketplace: ContractPackageHash,
+        config: Option<MarketplaceConfig>,
+    ) {
+        let mut args = RuntimeArgs::new();
+        args.insert("marketplace", marketplace).unwrap();
+        if let Some(config) = config {
+            args.insert("config", config).unwrap();
+        }
+
+        call_contract(context, contract, "set_marketplace_config", args)
+    }
+
+    pub fn set_settlement_required(
+        context: &mut TestContext,
+        contract: ContractHash,
+        required: bool,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "set_settlement_required",
+            runtime_args! {
+                "required" => required,
+            },
+        )
+    }
+
+    pub fn settlement_required(context: &mut TestContext, contract: ContractHash) -> bool {
+        call_contract_with_result::<bool>(
+            context,
+            contract,
+            "settlement_required",
+            runtime_args! {},
+        )
+    }
+
+    pub fn set_transfer_fee(context: &mut TestContext, contract: ContractHash, fee: Option<U512>) {
+        let mut args = RuntimeArgs::new();
+        if let Some(fee) = fee {
+            args.insert("fee", fee).unwrap();
+        }
+
+        call_contract(context, contract, "set_transfer_fee", args)
+    }
+
+    pub fn transfer_fee(context: &mut TestContext, contract: ContractHash) -> Option<U512> {
+        call_contract_with_result::<Option<U512>>(
+            context,
+            contract,
+            "transfer_fee",
+            runtime_args! {},
+        )
+    }
+
+    pub fn transferable_at(
+        context: &mut TestContext,
+        contract: ContractHash,
+        token_id: u64,
+    ) -> u64 {
+        call_contract_with_result::<u64>(
+            context,
+            contract,
+            "transferable_at",
+            runtime_args! {
+                "token_id" => token_id,
+            },
+        )
+    }
+
+    pub fn set_transfer_lock(context: &mut TestContext, contract: ContractHash, until: u64) {
+        call_contract(
+            context,
+            contract,
+            "set_transfer_lock",
+            runtime_args! {
+                "until" => until,
+            },
+        )
+    }
+
+    pub fn set_transfer_cooldown(context: &mut TestContext, contract: ContractHash, cooldown: u64) {
+        call_contract(
+            context,
+            contract,
+            "set_transfer_cooldown",
+            runtime_args! {
+                "cooldown" => cooldown,
+            },
+        )
+    }
+
+    /// Move `token_id` from `account` to `target_key` with the transfer session code, paying the
+    /// transfer fee from the account's main purse.
+    pub fn transfer_directly(
+        context: &mut TestContext,
+        account: AccountHash,
+        custodial_package: ContractPackageHash,
+        token_contract: ContractPackageHash,
+        token_id: u64,
+        target_key: Key,
+    ) {
+        let call_request = ExecuteRequestBuilder::module_bytes(
+            account,
+            CONTRACT_CEP82_TRANSFER_BYTES.to_vec(),
+            runtime_args! {
+                "custodial_package" => custodial_package,
+                "token_contract" => token_contract,
+                "token_id" => token_id,
+                "target_key" => target_key,
+            },
+        )
+        .with_block_time(context.block_time)
+        .build();
+
+        context
+            .builder
+            .exec(call_request)
+            .commit()
+            .expect_success_ex();
+    }
+
+    pub fn allow_transfer(
+        context: &mut TestContext,
+        contract: ContractHash,
+        token_id: u64,
+        source_key: Key,
+        target_key: Key,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "allow_transfer",
+            runtime_args! {
+                "token_id" => token_id,
+                "source_key" => source_key,
+                "target_key" => target_key,
+            },
+        )
+    }
+
+    pub fn exempt_from_royalty(
+        context: &mut TestContext,
+        contract: ContractHash,
+        account: Key,
+        until: u64,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "exempt_from_royalty",
+            runtime_args! {
+                "account" => account,
+                "until" => until,
+            },
+        )
+    }
+
+    pub fn set_recipient_list(
+        context: &mut TestContext,
+        contract: ContractHash,
+        list: Option<RecipientList>,
+    ) {
+        let mut args = RuntimeArgs::new();
+        if let Some(list) = list {
+            args.insert("list", list as u8).unwrap();
+        }
+
+        call_contract(context, contract, "set_recipient_list", args)
+    }
+
+    pub fn recipient_list(
+        context: &mut TestContext,
+        contract: ContractHash,
+    ) -> Option<RecipientList> {
+        call_contract_with_result::<Option<u8>>(
+            context,
+            contract,
+            "recipient_list",
+            runtime_args! {},
+        )
+        .map(|list| RecipientList::from_u8(list).unwrap())
+    }
+
+    pub fn add_recipients(
+        context: &mut TestContext,
+        contract: ContractHash,
+        list: RecipientList,
+        accounts: Vec<Key>,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "add_recipients",
+            runtime_args! {
+                "list" => list as u8,
+                "accounts" => accounts,
+            },
+        )
+    }
+
+    pub fn remove_recipients(
+        context: &mut TestContext,
+        contract: ContractHash,
+        list: RecipientList,
+        accounts: Vec<Key>,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "remove_recipients",
+            runtime_args! {
+                "list" => list as u8,
+                "accounts" => accounts,
+            },
+        )
+    }
+
+    pub fn is_recipient_listed(
+        context: &mut TestContext,
+        contract: ContractHash,
+        list: RecipientList,
+        account: Key,
+    ) -> bool {
+        call_contract_with_result::<bool>(
+            context,
+            contract,
+            "is_recipient_listed",
+            runtime_args! {
+                "list" => list as u8,
+                "account" => account,
+            },
+        )
+    }
+
+    pub fn can_receive(context: &mut TestContext, contract: ContractHash, account: Key) -> bool {
+        call_contract_with_result::<bool>(
+            context,
+            contract,
+            "can_receive",
+            runtime_args! {
+                "account" => account,
+            },
+        )
+    }
+
+    /// Balance of the purse the custodial contract collects royalties and fees in.
+    pub fn royalty_purse_balance(context: &mut TestContext, contract: ContractHash) -> U512 {
+        let royalty_purse = named_uref(context, contract, "royalty_purse");
+        context.builder.get_purse_balance(royalty_purse)
+    }
+
+    /// Read the event at `index` from the contract's event log.
+    pub fn event(context: &mut TestContext, contract: ContractHash, index: u64) -> EventRecord {
+        let events = named_uref(context, contract, "events");
+        let value = context
+            .builder
+            .query_dictionary_item(None, events, &index.to_string())
+            .unwrap()
+            .as_cl_value()
+            .cloned()
+            .unwrap();
+
+        bytesrepr::deserialize(value.inner_bytes().to_vec()).unwrap()
+    }
+
+    pub fn set_collection(
+        context: &mut TestContext,
+        contract: ContractHash,
+        token_contract: ContractPackageHash,
+    ) {
+        call_contract(
+            context,
+            contract,
+            "set_collection",
+            runtime_args! {
+                "token_contract" => token_contract,
+            },
+        )
+    }
+
+    pub fn collection(
+        context: &mut TestContext,
+        contract: ContractHash,
+    ) -> Option<ContractPackageHash> {
+        call_contract_with_result::<Option<ContractPackageHash>>(
+            context,
+            contract,
+            "collection",
+            runtime_args! {},
+        )
+    }
+
+    pub fn claim(context: &mut TestContext, contract: ContractHash, token_id: u64, owner: Key) {
+        call_contract(
+            context,
+            contract,
+            "claim",
+            runtime_args! {
+                "token_id" => token_id,
+                "owner" => owner,
+            },
+        )
+    }
+
+    pub fn set_claimant(
+        context: &mut TestContext,
+        contract: ContractHash,
+        token_id: u64,
+        claimant: Option<Key>,
+    ) {
+        let mut args = RuntimeArgs::new();
+        args.insert("token_id", token_id).unwrap();
+        if let Some(claimant) = claimant {
+            args.insert("new_claimant", claimant).unwrap();
+        }
+
+        call_contract(context, contract, "set_claimant", args)
+    }
+
+    pub fn claimant(
+        context: &mut TestContext,
+        contract: ContractHash,
+        token_id: u64,
+    ) -> Option<Key> {
+        call_contract_with_result::<Option<Key>>(
+            context,
+            contract,
+            "claimant",
             runtime_args! {
                 "token_id" => token_id,
-                "owner" => owner,
             },
         )
     }
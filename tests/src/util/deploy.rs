@@ -8,11 +8,11 @@ use casper_execution_engine::{
 };
 
 use super::{
-    cep78::{InstallerRequestBuilder, MetadataMutability, OwnershipMode},
+    cep78::{InstallerRequestBuilder, MetadataMutability, MintingMode, OwnershipMode},
     deploy_builder,
     state::RoyaltyStep,
-    CONTRACT_CEP78_BYTES, CONTRACT_CEP82_CUSTODIAL_BYTES, CONTRACT_CEP82_MARKETPLACE_BYTES,
-    CONTRACT_ERC20_BYTES,
+    CONTRACT_CEP78_BYTES, CONTRACT_CEP82_CUSTODIAL_BYTES, CONTRACT_CEP82_CUSTODIAL_V0_BYTES,
+    CONTRACT_CEP82_MARKETPLACE_BYTES, CONTRACT_CEP82_MARKETPLACE_V0_BYTES, CONTRACT_ERC20_BYTES,
 };
 use casper_types::{
     account::AccountHash, runtime_args, ContractHash, ContractPackageHash, Key, RuntimeArgs, U256,
@@ -120,6 +120,33 @@ where
     )
 }
 
+/// Install a collection anyone may mint tokens of, contracts included.
+pub fn deploy_cep78_with_public_minting<S>(
+    builder: &mut WasmTestBuilder<S>,
+    account: AccountHash,
+) -> (ContractHash, ContractPackageHash)
+where
+    S: StateProvider + CommitProvider,
+    EngineError: From<S::Error>,
+    <S as StateProvider>::Error: Into<ExecError>,
+{
+    let deploy_args = InstallerRequestBuilder::default()
+        .with_total_token_supply(100u64)
+        .with_ownership_mode(OwnershipMode::Transferable)
+        .with_identifier_mode(super::cep78::NFTIdentifierMode::Ordinal)
+        .with_metadata_mutability(MetadataMutability::Mutable)
+        .with_minting_mode(MintingMode::Public)
+        .build();
+
+    deploy_contract(
+        builder,
+        account,
+        CONTRACT_CEP78_BYTES,
+        deploy_args,
+        "cep78_contract_hash_nft-test",
+    )
+}
+
 pub fn deploy_cep82_marketplace<S>(
     builder: &mut WasmTestBuilder<S>,
     account: AccountHash,
@@ -140,6 +167,29 @@ where
     )
 }
 
+pub fn deploy_cep82_marketplace_with_fee<S>(
+    builder: &mut WasmTestBuilder<S>,
+    account: AccountHash,
+    platform_fee_bps: u64,
+) -> (ContractHash, ContractPackageHash)
+where
+    S: StateProvider + CommitProvider,
+    EngineError: From<S::Error>,
+    <S as StateProvider>::Error: Into<ExecError>,
+{
+    let deploy_args = runtime_args! {
+        "platform_fee_bps" => platform_fee_bps,
+    };
+
+    deploy_contract(
+        builder,
+        account,
+        CONTRACT_CEP82_MARKETPLACE_BYTES,
+        deploy_args,
+        "cep82_marketplace",
+    )
+}
+
 pub fn deploy_cep82_custodial<S>(
     builder: &mut WasmTestBuilder<S>,
     account: AccountHash,
@@ -166,3 +216,106 @@ where
         "cep82_custodial",
     )
 }
+
+/// Install the first release of the marketplace, which takes no arguments.
+pub fn deploy_cep82_marketplace_v0<S>(
+    builder: &mut WasmTestBuilder<S>,
+    account: AccountHash,
+) -> (ContractHash, ContractPackageHash)
+where
+    S: StateProvider + CommitProvider,
+    EngineError: From<S::Error>,
+    <S as StateProvider>::Error: Into<ExecError>,
+{
+    deploy_contract(
+        builder,
+        account,
+        CONTRACT_CEP82_MARKETPLACE_V0_BYTES,
+        runtime_args! {},
+        "cep82_marketplace",
+    )
+}
+
+/// Install the first release of the custodial contract.
+pub fn deploy_cep82_custodial_v0<S>(
+    builder: &mut WasmTestBuilder<S>,
+    account: AccountHash,
+    whitelisted_marketplaces: Vec<ContractPackageHash>,
+    royalty_structure: Vec<RoyaltyStep>,
+    manager: Key,
+) -> (ContractHash, ContractPackageHash)
+where
+    S: StateProvider + CommitProvider,
+    EngineError: From<S::Error>,
+    <S as StateProvider>::Error: Into<ExecError>,
+{
+    let deploy_args = runtime_args! {
+        "whitelisted_marketplaces" => whitelisted_marketplaces,
+        "royalty_structure" => royalty_structure,
+        "manager" => manager,
+    };
+
+    deploy_contract(
+        builder,
+        account,
+        CONTRACT_CEP82_CUSTODIAL_V0_BYTES,
+        deploy_args,
+        "cep82_custodial",
+    )
+}
+
+/// Add a second version to the marketplace `package` installed by `account`. Returns the new
+/// contract hash.
+pub fn upgrade_cep82_marketplace<S>(
+    builder: &mut WasmTestBuilder<S>,
+    account: AccountHash,
+    package: ContractPackageHash,
+) -> (ContractHash, ContractPackageHash)
+where
+    S: StateProvider + CommitProvider,
+    EngineError: From<S::Error>,
+    <S as StateProvider>::Error: Into<ExecError>,
+{
+    let deploy_args = runtime_args! {
+        "upgrade" => true,
+        "package_hash" => package,
+    };
+
+    deploy_contract(
+        builder,
+        account,
+        CONTRACT_CEP82_MARKETPLACE_BYTES,
+        deploy_args,
+        "cep82_marketplace_v2",
+    )
+}
+
+/// Add a second version to the custodial `package` installed by `account`, pointing it at
+/// `collection` if given. Returns the new contract hash.
+pub fn upgrade_cep82_custodial<S>(
+    builder: &mut WasmTestBuilder<S>,
+    account: AccountHash,
+    package: ContractPackageHash,
+    collection: Option<ContractPackageHash>,
+) -> (ContractHash, ContractPackageHash)
+where
+    S: StateProvider + CommitProvider,
+    EngineError: From<S::Error>,
+    <S as StateProvider>::Error: Into<ExecError>,
+{
+    let mut deploy_args = runtime_args! {
+        "upgrade" => true,
+        "package_hash" => package,
+    };
+    if let Some(collection) = collection {
+        deploy_args.insert("collection", collection).unwrap();
+    }
+
+    deploy_contract(
+        builder,
+        account,
+        CONTRACT_CEP82_CUSTODIAL_BYTES,
+        deploy_args,
+        "cep82_custodial_v2",
+    )
+}
@@ -0,0 +1,13 @@
+//! Decoding of the `ApiError::User` codes the contracts revert with.
+
+use contract_common::error::variant_name;
+use contract_custodial::CustodialError;
+use contract_marketplace::MarketError;
+
+/// Return the name of the error enum and variant an `ApiError::User` code stands for.
+pub fn describe(code: u16) -> Option<(&'static str, &'static str)> {
+    contract_common::error::describe(
+        code,
+        &[variant_name::<CustodialError>, variant_name::<MarketError>],
+    )
+}
@@ -1,6 +1,7 @@
 use casper_types::{
+    account::AccountHash,
     bytesrepr::{self, FromBytes, ToBytes},
-    CLTyped, U256, U512,
+    CLTyped, ContractPackageHash, U256, U512,
 };
 
 const ROYALTY_STEP_MINIMUM: u8 = 0;
@@ -81,3 +82,162 @@ impl RoyaltyStep {
         }]
     }
 }
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarketplaceConfig {
+    pub whitelisted: bool,
+    pub royalty_structure: Option<Vec<RoyaltyStep>>,
+    pub rebate_bps: u64,
+    pub rebate_account: Option<AccountHash>,
+}
+
+impl ToBytes for MarketplaceConfig {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = Vec::with_capacity(self.serialized_length());
+        result.append(&mut self.whitelisted.to_bytes()?);
+        result.append(&mut self.royalty_structure.to_bytes()?);
+        result.append(&mut self.rebate_bps.to_bytes()?);
+        result.append(&mut self.rebate_account.to_bytes()?);
+        Ok(result)
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.whitelisted.serialized_length()
+            + self.royalty_structure.serialized_length()
+            + self.rebate_bps.serialized_length()
+            + self.rebate_account.serialized_length()
+    }
+}
+
+impl FromBytes for MarketplaceConfig {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (whitelisted, bytes) = bool::from_bytes(bytes)?;
+        let (royalty_structure, bytes) = Option::<Vec<RoyaltyStep>>::from_bytes(bytes)?;
+        let (rebate_bps, bytes) = u64::from_bytes(bytes)?;
+        let (rebate_account, bytes) = Option::<AccountHash>::from_bytes(bytes)?;
+        Ok((
+            Self {
+                whitelisted,
+                royalty_structure,
+                rebate_bps,
+                rebate_account,
+            },
+            bytes,
+        ))
+    }
+}
+
+impl CLTyped for MarketplaceConfig {
+    fn cl_type() -> casper_types::CLType {
+        casper_types::CLType::Any
+    }
+}
+
+const TOKEN_IDENTIFIER_INDEX: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleItem {
+    pub nft_contract: ContractPackageHash,
+    pub token_id: u64,
+}
+
+impl ToBytes for BundleItem {
+    fn to_bytes(&self) -> Result<Vec<u8>, bytesrepr::Error> {
+        let mut result = Vec::with_capacity(self.serialized_length());
+        result.append(&mut self.nft_contract.to_bytes()?);
+        result.push(TOKEN_IDENTIFIER_INDEX);
+        result.append(&mut self.token_id.to_bytes()?);
+        Ok(result)
+    }
+
+    fn serialized_length(&self) -> usize {
+        self.nft_contract.serialized_length() + 1 + self.token_id.serialized_length()
+    }
+}
+
+impl CLTyped for BundleItem {
+    fn cl_type() -> casper_types::CLType {
+        casper_types::CLType::Any
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoyaltyMode {
+    SellerPays = 0,
+    BuyerPays = 1,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Admin = 0,
+    Manager = 1,
+    Treasurer = 2,
+    Pauser = 3,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecipientList {
+    Allow = 0,
+    Deny = 1,
+}
+
+impl RecipientList {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Allow),
+            1 => Some(Self::Deny),
+            _ => None,
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketplaceMode {
+    Open = 0,
+    Whitelist = 1,
+    Denylist = 2,
+}
+
+impl MarketplaceMode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Open),
+            1 => Some(Self::Whitelist),
+            2 => Some(Self::Denylist),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaleQuote {
+    pub price: U512,
+    pub royalty: U512,
+    pub platform_fee: U512,
+    pub seller_proceeds: U512,
+    pub total_due: U512,
+}
+
+impl FromBytes for SaleQuote {
+    fn from_bytes(bytes: &[u8]) -> Result<(Self, &[u8]), bytesrepr::Error> {
+        let (price, bytes) = U512::from_bytes(bytes)?;
+        let (royalty, bytes) = U512::from_bytes(bytes)?;
+        let (platform_fee, bytes) = U512::from_bytes(bytes)?;
+        let (seller_proceeds, bytes) = U512::from_bytes(bytes)?;
+        let (total_due, bytes) = U512::from_bytes(bytes)?;
+        Ok((
+            Self {
+                price,
+                royalty,
+                platform_fee,
+                seller_proceeds,
+                total_due,
+            },
+            bytes,
+        ))
+    }
+}